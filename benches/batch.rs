@@ -141,6 +141,114 @@ mod buffer_ordered {
     }
 }
 
+#[divan::bench_group]
+mod futures_ordered {
+    use futures_buffered::FuturesOrderedBounded;
+    use futures_util::{stream::FuturesOrdered, StreamExt};
+    use std::time::Duration;
+
+    const SIZES: [usize; 3] = [16, 64, 256];
+
+    /// Futures within each batch complete in reverse order, so every output but the
+    /// last has to wait for the ones before it.
+    async fn sleep_rev(i: usize, n: usize) {
+        tokio::time::sleep(Duration::from_micros(10 * (n - i % n) as u64)).await
+    }
+
+    #[divan::bench(args = SIZES)]
+    fn futures(n: usize) {
+        // setup a tokio runtime for our tests
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut queue = FuturesOrdered::new();
+
+        let total = n * n;
+        for i in 0..n {
+            queue.push_back(sleep_rev(i, n))
+        }
+        for i in n..total {
+            runtime.block_on(queue.next());
+            queue.push_back(sleep_rev(i, n))
+        }
+        for _ in 0..n {
+            runtime.block_on(queue.next());
+        }
+    }
+
+    #[divan::bench(args = SIZES)]
+    fn futures_buffered(n: usize) {
+        // setup a tokio runtime for our tests
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut queue = FuturesOrderedBounded::new(n);
+
+        let total = n * n;
+        for i in 0..n {
+            queue.push_back(sleep_rev(i, n))
+        }
+        for i in n..total {
+            runtime.block_on(queue.next());
+            queue.push_back(sleep_rev(i, n))
+        }
+        for _ in 0..n {
+            runtime.block_on(queue.next());
+        }
+    }
+
+    #[divan::bench(args = SIZES)]
+    fn futures_buffered_window(n: usize) {
+        // setup a tokio runtime for our tests
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut queue = FuturesOrderedBounded::new(n);
+        queue.set_reorder_window(n);
+
+        let total = n * n;
+        for i in 0..n {
+            queue.push_back(sleep_rev(i, n))
+        }
+        for i in n..total {
+            runtime.block_on(queue.next());
+            queue.push_back(sleep_rev(i, n))
+        }
+        for _ in 0..n {
+            runtime.block_on(queue.next());
+        }
+    }
+
+    #[divan::bench(args = SIZES)]
+    fn futures_buffered_unbounded(n: usize) {
+        // setup a tokio runtime for our tests
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut queue = futures_buffered::FuturesOrdered::new();
+
+        let total = n * n;
+        for i in 0..n {
+            queue.push_back(sleep_rev(i, n))
+        }
+        for i in n..total {
+            runtime.block_on(queue.next());
+            queue.push_back(sleep_rev(i, n))
+        }
+        for _ in 0..n {
+            runtime.block_on(queue.next());
+        }
+    }
+}
+
 #[divan::bench_group]
 mod join {
     use crate::sleep;
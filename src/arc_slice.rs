@@ -4,7 +4,7 @@ use core::{
     marker::PhantomData,
     ops::Deref,
     ptr::{self, drop_in_place, NonNull},
-    sync::atomic::{self, AtomicUsize},
+    sync::atomic::{self, AtomicBool, AtomicUsize},
     task::Waker,
 };
 use futures_util::task::AtomicWaker;
@@ -33,7 +33,8 @@ use futures_util::task::AtomicWaker;
 /// For example, if we have an ArcSlot pointing at the number 2, we can count back to pointer 2 `usize`s + [`ArcSliceInnerMeta`] to find
 /// the start of the [`ArcSlice`], and then we can insert `2` into the list of futures to poll, finally calling `waker.wake()`.
 ///
-/// Each slot also forms part of a linked list.
+/// Each slot also forms part of a linked list. A slot is only in the list once at a time - it is marked as
+/// queued when it is pushed, and is only pushed again after it has been popped.
 pub(crate) struct ArcSlice {
     ptr: NonNull<ArcSliceInner>,
     phantom: PhantomData<ArcSliceInner>,
@@ -54,6 +55,8 @@ pub(crate) struct ArcSliceInnerMeta {
     list_head: AtomicUsize,
     list_tail: UnsafeCell<usize>,
     len: usize,
+    #[cfg(feature = "metrics")]
+    wakes: AtomicUsize,
 }
 
 // This is repr(C) to future-proof against possible field-reordering, which
@@ -63,6 +66,10 @@ pub(crate) struct ArcSliceInnerMeta {
 pub(crate) struct ArcSlotInner {
     index: usize,
     next: AtomicUsize,
+    queued: AtomicBool,
+    // the number of live wakers for this slot, used to detect lost wakeups
+    #[cfg(feature = "stall-detector")]
+    wakers: AtomicUsize,
 }
 
 const fn __assert_send_sync<T: Send + Sync>() {}
@@ -95,7 +102,45 @@ impl ArcSlice {
         self.meta.waker.register(waker)
     }
 
-    fn get(&self, index: usize) -> Waker {
+    /// Returns the number of times the wakers for this [`ArcSlice`] have been woken.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn wakes(&self) -> u64 {
+        self.meta.wakes.load(atomic::Ordering::Relaxed) as u64
+    }
+
+    /// Returns the number of bytes allocated for this [`ArcSlice`].
+    #[cfg(feature = "metrics")]
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        Self::layout(self.meta.len).size()
+    }
+
+    /// Returns `true` if the slot is waiting in the queue to be polled.
+    #[cfg(feature = "stall-detector")]
+    pub(crate) fn is_queued(&self, index: usize) -> bool {
+        index < self.meta.len && self.slice[index].queued.load(atomic::Ordering::Acquire)
+    }
+
+    /// Returns the number of wakers for the slot that are still alive.
+    #[cfg(feature = "stall-detector")]
+    pub(crate) fn slot_wakers(&self, index: usize) -> usize {
+        if index < self.meta.len {
+            self.slice[index].wakers.load(atomic::Ordering::Acquire)
+        } else {
+            0
+        }
+    }
+
+    /// Returns `true` if there are no wakers referencing this [`ArcSlice`].
+    pub(crate) fn is_unique(&self) -> bool {
+        self.meta.strong.load(atomic::Ordering::Acquire) == 1
+    }
+
+    /// Creates the waker for the given slot index.
+    ///
+    /// # Panics
+    /// Panics if the index is out of bounds
+    pub(crate) fn waker(&self, index: usize) -> Waker {
+        assert!(index < self.meta.len, "waker index out of bounds");
         self.meta.inc_strong();
         let ptr: *mut ArcSliceInner = NonNull::as_ptr(self.ptr);
 
@@ -108,6 +153,10 @@ impl ArcSlice {
             index,
             "the slot should point at our index"
         );
+        #[cfg(feature = "stall-detector")]
+        unsafe { &*slot }
+            .wakers
+            .fetch_add(1, atomic::Ordering::Relaxed);
         slot::waker(slot)
     }
 
@@ -115,20 +164,36 @@ impl ArcSlice {
     ///
     /// Note that this is unsafe as it required mutual exclusion (only one
     /// thread can call this) to be guaranteed elsewhere.
-    pub(crate) unsafe fn pop(&self) -> ReadySlot<(usize, Waker)> {
-        match ArcSliceInner::pop(self) {
-            ReadySlot::Ready(i) => ReadySlot::Ready((i, self.get(i))),
-            ReadySlot::Inconsistent => ReadySlot::Inconsistent,
-            ReadySlot::None => ReadySlot::None,
-        }
+    pub(crate) unsafe fn pop(&self) -> ReadySlot<usize> {
+        ArcSliceInner::pop(self)
     }
 }
 
 impl ArcSliceInner {
-    /// The push function from the 1024cores intrusive MPSC queue algorithm.
+    /// Pushes the slot into the queue, unless it is already queued.
+    ///
+    /// Returns `false` if the slot was already queued.
     ///
     /// Safety: index must be within capacity
-    pub(crate) unsafe fn push(&self, index: usize) {
+    pub(crate) unsafe fn push(&self, index: usize) -> bool {
+        // This pairs with the swap in `pop`. If we see the slot as still queued,
+        // then the pop has not happened yet, so the future will be polled
+        // after any of the changes made before this wake.
+        let queued = self
+            .slice
+            .get_unchecked(index)
+            .queued
+            .swap(true, atomic::Ordering::SeqCst);
+        if !queued {
+            self.enqueue(index);
+        }
+        !queued
+    }
+
+    /// The push function from the 1024cores intrusive MPSC queue algorithm.
+    ///
+    /// Safety: index must be within capacity, and not currently in the queue
+    unsafe fn enqueue(&self, index: usize) {
         self.slice
             .get_unchecked(index)
             .next
@@ -165,14 +230,14 @@ impl ArcSliceInner {
         if next <= self.meta.len {
             *self.meta.list_tail.get() = next;
             debug_assert!(tail != self.meta.len);
-            return ReadySlot::Ready(tail);
+            return ReadySlot::Ready(self.dequeued(tail));
         }
 
         if self.meta.list_head.load(atomic::Ordering::Acquire) != tail {
             return ReadySlot::Inconsistent;
         }
 
-        self.push(self.meta.len);
+        self.enqueue(self.meta.len);
 
         next = self
             .slice
@@ -182,11 +247,25 @@ impl ArcSliceInner {
 
         if next <= self.meta.len {
             *self.meta.list_tail.get() = next;
-            return ReadySlot::Ready(tail);
+            return ReadySlot::Ready(self.dequeued(tail));
         }
 
         ReadySlot::Inconsistent
     }
+
+    /// Marks the popped slot as no longer queued, so that it can be woken again.
+    ///
+    /// This must happen before the future in the slot is polled,
+    /// otherwise a wake during the poll could be lost.
+    unsafe fn dequeued(&self, index: usize) -> usize {
+        let queued = self
+            .slice
+            .get_unchecked(index)
+            .queued
+            .swap(false, atomic::Ordering::SeqCst);
+        debug_assert!(queued, "popped slots should be queued");
+        index
+    }
 }
 
 pub(crate) enum ReadySlot<T> {
@@ -274,6 +353,10 @@ mod slot {
         // Increment the reference count of the arc to clone it.
         unsafe fn clone_waker(waker: *const ()) -> RawWaker {
             meta_ref(waker.cast()).inc_strong();
+            #[cfg(feature = "stall-detector")]
+            (*waker.cast::<ArcSlotInner>())
+                .wakers
+                .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
             RawWaker::new(waker, &VTABLE)
         }
 
@@ -284,16 +367,28 @@ mod slot {
         }
 
         // Find the `ArcSliceInnerMeta` and push the current index value into it,
-        // then call the stored waker to trigger a poll
+        // then call the stored waker to trigger a poll.
+        // If the slot was already queued, the stored waker has already been woken for it.
         unsafe fn wake_by_ref(waker: *const ()) {
             let slot = waker.cast();
             let inner = inner_ref(slot);
-            inner.push((*slot).index);
-            inner.meta.waker.wake();
+            #[cfg(feature = "metrics")]
+            inner
+                .meta
+                .wakes
+                .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+            if inner.push((*slot).index) {
+                inner.meta.waker.wake();
+            }
         }
 
         // Decrement the reference count of the Arc on drop
         unsafe fn drop_waker(waker: *const ()) {
+            // this must happen before the strong count is released
+            #[cfg(feature = "stall-detector")]
+            (*waker.cast::<ArcSlotInner>())
+                .wakers
+                .fetch_sub(1, core::sync::atomic::Ordering::Release);
             let meta = meta_ref(waker.cast());
             if meta.dec_strong() {
                 unsafe {
@@ -434,14 +529,20 @@ impl ArcSlice {
                 list_head: AtomicUsize::new(cap),
                 list_tail: UnsafeCell::new(cap),
                 waker: AtomicWaker::new(),
+                #[cfg(feature = "metrics")]
+                wakes: AtomicUsize::new(0),
             };
             ptr::write(ptr::addr_of_mut!((*inner).meta), meta);
-            for i in 0..cap {
+            // the final slot is the stub node of the queue, which also needs initialising
+            for i in 0..=cap {
                 ptr::write(
                     ptr::addr_of_mut!((*inner).slice[i]),
                     ArcSlotInner {
                         index: i,
                         next: AtomicUsize::new(cap + 1),
+                        queued: AtomicBool::new(false),
+                        #[cfg(feature = "stall-detector")]
+                        wakers: AtomicUsize::new(0),
                     },
                 );
             }
@@ -0,0 +1,100 @@
+use core::task::{Context, Poll};
+
+/// Controls how many futures a set polls in one go, before yielding back to the executor.
+///
+/// Polling a set keeps polling its woken futures until none are left. A future that keeps
+/// waking itself could then stop the rest of the task from making progress, so once the
+/// budget is used up, the set wakes itself and returns [`Poll::Pending`].
+///
+/// Smaller budgets let other work in the same task run sooner, which suits latency-sensitive
+/// services. Larger budgets suit batch jobs, which would rather not yield.
+///
+/// # Example
+///
+/// ```
+/// use futures_buffered::{FuturesUnordered, PollBudget};
+/// use std::future::Ready;
+///
+/// let mut set = FuturesUnordered::<Ready<i32>>::new();
+/// set.set_poll_budget(PollBudget::new(8));
+/// assert_eq!(set.poll_budget().limit(), 8);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollBudget {
+    pub(crate) limit: usize,
+    #[cfg(feature = "tokio-coop")]
+    pub(crate) tokio_coop: bool,
+}
+
+impl Default for PollBudget {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl PollBudget {
+    pub(crate) const DEFAULT: Self = Self::new(61);
+
+    /// Creates a budget that polls at most `limit` futures in one go.
+    ///
+    /// # Panics
+    /// This method will panic if `limit` is 0
+    #[track_caller]
+    pub const fn new(limit: usize) -> Self {
+        assert!(limit > 0, "the poll budget must be at least 1");
+        Self {
+            limit,
+            #[cfg(feature = "tokio-coop")]
+            tokio_coop: false,
+        }
+    }
+
+    /// Also spend tokio's [cooperative scheduling](https://docs.rs/tokio/latest/tokio/task/coop/index.html)
+    /// budget for every future that completes, and yield once the task has used it up.
+    ///
+    /// This has no effect outside of a tokio runtime.
+    ///
+    /// Requires the `tokio-coop` feature.
+    #[cfg(feature = "tokio-coop")]
+    pub const fn tokio_coop(mut self, enabled: bool) -> Self {
+        self.tokio_coop = enabled;
+        self
+    }
+
+    /// Returns the maximum number of futures polled in one go.
+    pub const fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Checks with the runtime that the task may poll another future.
+    #[inline(always)]
+    pub(crate) fn proceed(&self, _cx: &mut Context<'_>) -> Poll<Progress> {
+        #[cfg(feature = "tokio-coop")]
+        if self.tokio_coop {
+            return tokio::task::coop::poll_proceed(_cx).map(|restore| Progress {
+                restore: Some(restore),
+            });
+        }
+        Poll::Ready(Progress {
+            #[cfg(feature = "tokio-coop")]
+            restore: None,
+        })
+    }
+}
+
+/// Returned by [`PollBudget::proceed`]. Unless [`Progress::made_progress`] is called,
+/// the runtime budget is given back when this is dropped.
+pub(crate) struct Progress {
+    #[cfg(feature = "tokio-coop")]
+    restore: Option<tokio::task::coop::RestoreOnPending>,
+}
+
+impl Progress {
+    #[inline(always)]
+    pub(crate) fn made_progress(&self) {
+        #[cfg(feature = "tokio-coop")]
+        if let Some(restore) = &self.restore {
+            restore.made_progress();
+        }
+    }
+}
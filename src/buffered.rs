@@ -1,15 +1,26 @@
+use crate::instrument::NoSpan;
 use crate::FuturesOrderedBounded;
 use crate::FuturesUnorderedBounded;
+use crate::{HeadPolicy, Timer};
 use core::future::Future;
 use futures_core::Stream;
 
+mod adaptive;
+mod by_key;
 mod for_each;
+mod limit;
 mod ordered;
 mod unordered;
+mod weighted;
 
+pub use adaptive::{Adaptive, Aimd};
+pub use by_key::BufferedOrderedByKey;
 pub use for_each::ForEachConcurrent;
-pub use ordered::BufferedOrdered;
+pub use limit::ConcurrencyLimit;
+pub(crate) use limit::LimitListener;
+pub use ordered::{BufferedOrdered, BufferedOrderedHeadOfLine};
 pub use unordered::BufferUnordered;
+pub use weighted::BufferUnorderedWeighted;
 
 impl<T: ?Sized + Stream> BufferedStreamExt for T {}
 
@@ -33,9 +44,124 @@ pub trait BufferedStreamExt: Stream {
         BufferedOrdered {
             stream: Some(self),
             in_progress_queue: FuturesOrderedBounded::new(n),
+            limit: None,
         }
     }
 
+    /// An adaptor for creating a buffered list of pending futures, with a limit
+    /// that can be changed while the stream is running.
+    ///
+    /// This behaves like [`buffered_ordered`](BufferedStreamExt::buffered_ordered), except
+    /// new futures are only started while fewer than [`ConcurrencyLimit::get`] futures are
+    /// in-flight.
+    fn buffered_ordered_with_limit(self, limit: ConcurrencyLimit) -> BufferedOrdered<Self>
+    where
+        Self::Item: Future,
+        Self: Sized,
+    {
+        BufferedOrdered {
+            stream: Some(self),
+            in_progress_queue: FuturesOrderedBounded::new(limit.get()),
+            limit: Some(limit.listen()),
+        }
+    }
+
+    /// An adaptor for creating a buffered list of pending futures, which holds at most
+    /// `window` outputs out of order.
+    ///
+    /// This behaves like [`buffered_ordered`](BufferedStreamExt::buffered_ordered), except
+    /// no new futures are started while `window` futures are in-flight or waiting for an
+    /// earlier future to complete. See [`FuturesOrderedBounded::set_reorder_window`].
+    fn buffered_ordered_with_window(self, n: usize, window: usize) -> BufferedOrdered<Self>
+    where
+        Self::Item: Future,
+        Self: Sized,
+    {
+        let mut in_progress_queue = FuturesOrderedBounded::new(n);
+        in_progress_queue.set_reorder_window(window);
+        BufferedOrdered {
+            stream: Some(self),
+            in_progress_queue,
+            limit: None,
+        }
+    }
+
+    /// An adaptor for creating a buffered list of pending futures, which gives up on
+    /// a head future that holds up the outputs behind it.
+    ///
+    /// This behaves like [`buffered_ordered`](BufferedStreamExt::buffered_ordered), except
+    /// each output is wrapped in `Ok`. Once the [`HeadPolicy`] gives up on the head future,
+    /// it is dropped and, depending on the [`HeadAction`](crate::HeadAction), either skipped
+    /// or replaced by `Err(Stalled)`.
+    fn buffered_ordered_with_head_policy<T: Timer>(
+        self,
+        n: usize,
+        policy: HeadPolicy<T>,
+    ) -> BufferedOrderedHeadOfLine<Self, T>
+    where
+        Self::Item: Future,
+        Self: Sized,
+    {
+        self.buffered_ordered(n).with_head_policy(policy)
+    }
+
+    /// An adaptor for creating a buffered list of pending futures, which keeps the
+    /// order of outputs within each key.
+    ///
+    /// Each future is given a key by `key_fn`. Up to `n` futures run concurrently, but never
+    /// two with the same key. A future whose key is busy waits until the earlier futures
+    /// with that key have completed, so the outputs for each key are returned in the same
+    /// order as the underlying stream. Outputs for different keys are returned in the order
+    /// in which they complete.
+    ///
+    /// No more than `n` futures will wait for their key at any point in time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::BufferedStreamExt;
+    /// use std::{future::Future, pin::Pin, task::{Context, Poll}};
+    ///
+    /// struct Event {
+    ///     user: &'static str,
+    ///     id: u32,
+    /// }
+    ///
+    /// impl Future for Event {
+    ///     type Output = (&'static str, u32);
+    ///
+    ///     fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+    ///         Poll::Ready((self.user, self.id))
+    ///     }
+    /// }
+    ///
+    /// let events = [("alice", 1), ("bob", 2), ("alice", 3)];
+    /// let stream = stream::iter(events).map(|(user, id)| Event { user, id });
+    ///
+    /// let mut outputs: Vec<_> = stream
+    ///     .buffered_ordered_by_key(2, |event: &Event| event.user)
+    ///     .collect()
+    ///     .await;
+    /// outputs.sort_by_key(|(user, _)| *user);
+    /// assert_eq!(outputs, [("alice", 1), ("alice", 3), ("bob", 2)]);
+    /// # });
+    /// ```
+    fn buffered_ordered_by_key<K, KF>(
+        self,
+        n: usize,
+        key_fn: KF,
+    ) -> BufferedOrderedByKey<Self, K, KF>
+    where
+        Self::Item: Future,
+        Self: Sized,
+        K: Ord + Clone,
+        KF: FnMut(&Self::Item) -> K,
+    {
+        BufferedOrderedByKey::new(self, n, key_fn)
+    }
+
     /// An adaptor for creating a buffered list of pending futures (unordered).
     ///
     /// If this stream's item can be converted into a future, then this adaptor
@@ -79,9 +205,153 @@ pub trait BufferedStreamExt: Stream {
         BufferUnordered {
             stream: Some(self),
             in_progress_queue: FuturesUnorderedBounded::new(n),
+            limit: None,
+            span_fn: NoSpan,
+        }
+    }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), where
+    /// each future is polled inside of a [`tracing::Span`](https://docs.rs/tracing/latest/tracing/struct.Span.html).
+    ///
+    /// This behaves like [`buffered_unordered`](BufferedStreamExt::buffered_unordered), except
+    /// `span_fn` is called with each future from the stream to create the span it is polled in.
+    ///
+    /// Requires the `tracing` feature.
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::BufferedStreamExt;
+    /// use std::future::ready;
+    ///
+    /// let stream_of_futures = stream::iter([ready(1), ready(2)]);
+    /// let buffered = stream_of_futures
+    ///     .buffered_unordered_with_span(10, |_| tracing::info_span!("request"));
+    ///
+    /// assert_eq!(buffered.count().await, 2);
+    /// # })
+    /// ```
+    #[cfg(feature = "tracing")]
+    fn buffered_unordered_with_span<SF>(self, n: usize, span_fn: SF) -> BufferUnordered<Self, SF>
+    where
+        Self::Item: Future,
+        Self: Sized,
+        SF: FnMut(&Self::Item) -> tracing::Span,
+    {
+        BufferUnordered {
+            stream: Some(self),
+            in_progress_queue: FuturesUnorderedBounded::new(n),
+            limit: None,
+            span_fn,
         }
     }
 
+    /// An adaptor for creating a buffered list of pending futures (unordered), with
+    /// a limit that can be changed while the stream is running.
+    ///
+    /// This behaves like [`buffered_unordered`](BufferedStreamExt::buffered_unordered), except
+    /// new futures are only started while fewer than [`ConcurrencyLimit::get`] futures are
+    /// in-flight.
+    ///
+    /// See [`ConcurrencyLimit`] for an example
+    fn buffered_unordered_with_limit(self, limit: ConcurrencyLimit) -> BufferUnordered<Self>
+    where
+        Self::Item: Future,
+        Self: Sized,
+    {
+        BufferUnordered {
+            stream: Some(self),
+            in_progress_queue: FuturesUnorderedBounded::new(limit.get()),
+            limit: Some(limit.listen()),
+            span_fn: NoSpan,
+        }
+    }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), where the
+    /// concurrency limit adapts to how the futures are behaving.
+    ///
+    /// This behaves like [`buffered_unordered`](BufferedStreamExt::buffered_unordered), but
+    /// `classify` is called on each output to decide whether it was successful. The limit is
+    /// then raised or lowered according to the given [`Aimd`] configuration.
+    ///
+    /// Room for the maximum limit is allocated up front, so changing the limit never allocates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::{Aimd, BufferedStreamExt};
+    /// use std::future::ready;
+    ///
+    /// let mut buffered = stream::iter((0..10).map(ready))
+    ///     .buffered_unordered_adaptive(Aimd::new(1, 4), |x: &i32| *x < 8);
+    ///
+    /// while let Some(_) = buffered.next().await {
+    ///     assert!((1..=4).contains(&buffered.limit()));
+    /// }
+    /// assert_eq!(buffered.limit(), 1);
+    /// # })
+    /// ```
+    fn buffered_unordered_adaptive<C>(
+        self,
+        aimd: Aimd,
+        classify: C,
+    ) -> Adaptive<BufferUnordered<Self>, C>
+    where
+        Self::Item: Future,
+        C: FnMut(&<Self::Item as Future>::Output) -> bool,
+        Self: Sized,
+    {
+        let (limit, in_progress_queue) = aimd.limited_set();
+        let stream = BufferUnordered {
+            stream: Some(self),
+            in_progress_queue,
+            limit: Some(limit.listen()),
+            span_fn: NoSpan,
+        };
+        Adaptive::new(stream, limit, aimd, classify)
+    }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), limited
+    /// by the summed weight of the futures rather than their count.
+    ///
+    /// `weight_fn` is called on each item from this stream to determine its weight.
+    /// New futures are only started while the summed weight of the in-flight futures
+    /// stays within `budget`. The weight of a future is released once it completes.
+    /// A single future heavier than the whole budget is still started once nothing
+    /// else is in-flight.
+    ///
+    /// The returned stream will be a stream of each future's output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::BufferedStreamExt;
+    ///
+    /// let uploads = stream::iter(["a.txt", "video.mp4", "b.txt"])
+    ///     .map(|name| async move { name.len() });
+    ///
+    /// // every upload reserves 4 out of a budget of 10, so at most 2 run at once
+    /// let buffered = uploads.buffered_unordered_weighted(10, |_| 4);
+    /// assert_eq!(buffered.count().await, 3);
+    /// # })
+    /// ```
+    fn buffered_unordered_weighted<W>(
+        self,
+        budget: usize,
+        weight_fn: W,
+    ) -> BufferUnorderedWeighted<Self, W>
+    where
+        Self::Item: Future,
+        W: FnMut(&Self::Item) -> usize,
+        Self: Sized,
+    {
+        BufferUnorderedWeighted::new(self, budget, weight_fn)
+    }
+
     /// Runs this stream to completion, executing the provided asynchronous
     /// closure for each element on the stream concurrently as elements become
     /// available.
@@ -133,6 +403,31 @@ pub trait BufferedStreamExt: Stream {
         Fut: Future<Output = ()>,
         Self: Sized,
     {
-        ForEachConcurrent::new(self, limit, f)
+        ForEachConcurrent::new(self, limit, f, NoSpan)
+    }
+
+    /// Runs this stream to completion, executing the provided asynchronous
+    /// closure for each element on the stream concurrently, where each future is polled
+    /// inside of a [`tracing::Span`](https://docs.rs/tracing/latest/tracing/struct.Span.html).
+    ///
+    /// This behaves like [`for_each_concurrent`](BufferedStreamExt::for_each_concurrent), except
+    /// `span_fn` is called with each future created by `f` to create the span that it is
+    /// polled in.
+    ///
+    /// Requires the `tracing` feature.
+    #[cfg(feature = "tracing")]
+    fn for_each_concurrent_with_span<Fut, F, SF>(
+        self,
+        limit: usize,
+        span_fn: SF,
+        f: F,
+    ) -> ForEachConcurrent<Self, Fut, F, SF>
+    where
+        F: FnMut(Self::Item) -> Fut,
+        Fut: Future<Output = ()>,
+        SF: FnMut(&Fut) -> tracing::Span,
+        Self: Sized,
+    {
+        ForEachConcurrent::new(self, limit, f, span_fn)
     }
 }
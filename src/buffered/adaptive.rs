@@ -0,0 +1,253 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{
+    BufferUnordered, ConcurrencyLimit, FuturesUnorderedBounded, PollBudget, TryBufferUnordered,
+    TryStream,
+};
+
+/// Configuration for an additive-increase/multiplicative-decrease (AIMD) concurrency limit.
+///
+/// Every successful output raises the limit by [`increase`](Aimd::increase), and every
+/// failed output multiplies the limit by [`backoff`](Aimd::backoff). The limit always
+/// stays within `min..=max`.
+///
+/// See [`buffered_unordered_adaptive`](crate::BufferedStreamExt::buffered_unordered_adaptive)
+/// and [`try_buffered_unordered_adaptive`](crate::BufferedTryStreamExt::try_buffered_unordered_adaptive).
+#[derive(Debug, Clone, Copy)]
+pub struct Aimd {
+    min: usize,
+    max: usize,
+    initial: usize,
+    increase: usize,
+    backoff: f64,
+}
+
+impl Aimd {
+    /// Creates a new AIMD configuration with the given bounds on the limit.
+    ///
+    /// The limit starts at `min`, increases by 1 on success and halves on failure.
+    ///
+    /// # Panics
+    /// This method will panic if `min` is 0, or if `min` is greater than `max`
+    #[track_caller]
+    pub fn new(min: usize, max: usize) -> Self {
+        assert!(min > 0, "the minimum concurrency limit must be at least 1");
+        assert!(
+            min <= max,
+            "the minimum concurrency limit must not exceed the maximum"
+        );
+        Self {
+            min,
+            max,
+            initial: min,
+            increase: 1,
+            backoff: 0.5,
+        }
+    }
+
+    /// Sets the limit to start with. It will be clamped to `min..=max`.
+    pub fn initial(mut self, initial: usize) -> Self {
+        self.initial = initial.clamp(self.min, self.max);
+        self
+    }
+
+    /// Sets how much the limit grows after each successful output.
+    pub fn increase(mut self, increase: usize) -> Self {
+        self.increase = increase;
+        self
+    }
+
+    /// Sets the factor the limit is multiplied by after each failed output.
+    ///
+    /// # Panics
+    /// This method will panic if `backoff` is not within `0.0..1.0`
+    #[track_caller]
+    pub fn backoff(mut self, backoff: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&backoff),
+            "the backoff factor must be within 0.0..1.0"
+        );
+        self.backoff = backoff;
+        self
+    }
+
+    /// Creates the limit, along with a set that has room for the largest limit,
+    /// so that changing the limit never has to allocate.
+    pub(crate) fn limited_set<F>(&self) -> (ConcurrencyLimit, FuturesUnorderedBounded<F>) {
+        let mut set = FuturesUnorderedBounded::new(self.max);
+        set.set_capacity(self.initial);
+        (ConcurrencyLimit::new(self.initial), set)
+    }
+
+    fn next_limit(&self, limit: usize, success: bool) -> usize {
+        let limit = if success {
+            limit.saturating_add(self.increase)
+        } else {
+            (limit as f64 * self.backoff) as usize
+        };
+        limit.clamp(self.min, self.max)
+    }
+}
+
+pin_project!(
+    /// Stream for the [`buffered_unordered_adaptive`](crate::BufferedStreamExt::buffered_unordered_adaptive),
+    /// [`try_buffered_unordered_adaptive`](crate::BufferedTryStreamExt::try_buffered_unordered_adaptive)
+    /// and [`try_buffered_unordered_adaptive_by`](crate::BufferedTryStreamExt::try_buffered_unordered_adaptive_by)
+    /// methods.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Adaptive<S, C> {
+        #[pin]
+        stream: S,
+        limit: ConcurrencyLimit,
+        aimd: Aimd,
+        classify: C,
+    }
+);
+
+impl<S, C> Adaptive<S, C> {
+    pub(crate) fn new(stream: S, limit: ConcurrencyLimit, aimd: Aimd, classify: C) -> Self {
+        Self {
+            stream,
+            limit,
+            aimd,
+            classify,
+        }
+    }
+
+    /// Returns the current concurrency limit.
+    pub fn limit(&self) -> usize {
+        self.limit.get()
+    }
+}
+
+impl<S: Stream, C> Adaptive<BufferUnordered<S>, C> {
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.stream = self.stream.with_poll_budget(budget);
+        self
+    }
+}
+
+impl<S: TryStream, C> Adaptive<TryBufferUnordered<S>, C> {
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.stream = self.stream.with_poll_budget(budget);
+        self
+    }
+}
+
+impl<S, C> Stream for Adaptive<S, C>
+where
+    S: Stream,
+    C: FnMut(&S::Item) -> bool,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let res = this.stream.poll_next(cx);
+        if let Poll::Ready(Some(item)) = &res {
+            let success = (this.classify)(item);
+            // the stream picks up the new limit the next time it is polled
+            this.limit
+                .store(this.aimd.next_limit(this.limit.get(), success));
+        }
+        res
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferedStreamExt, BufferedTryStreamExt};
+    use core::future::ready;
+    use futures::{stream, StreamExt};
+
+    #[test]
+    fn aimd() {
+        let aimd = Aimd::new(2, 10).increase(2).backoff(0.25);
+        assert_eq!(aimd.next_limit(2, true), 4);
+        assert_eq!(aimd.next_limit(9, true), 10);
+        assert_eq!(aimd.next_limit(10, false), 2);
+        assert_eq!(aimd.next_limit(3, false), 2);
+        assert_eq!(Aimd::new(2, 10).initial(20).initial, 10);
+    }
+
+    #[test]
+    fn buffered_unordered_adaptive() {
+        let aimd = Aimd::new(1, 4);
+        let mut buffered =
+            stream::iter((0..6).map(ready)).buffered_unordered_adaptive(aimd, |x: &i32| *x != 3);
+        assert_eq!(buffered.limit(), 1);
+
+        let mut limits = vec![];
+        while futures::executor::block_on(buffered.next()).is_some() {
+            limits.push(buffered.limit());
+        }
+        assert_eq!(limits, [2, 3, 4, 2, 3, 4]);
+    }
+
+    #[test]
+    fn ramp_without_allocating() {
+        let aimd = Aimd::new(1, 64);
+        let mut buffered =
+            stream::iter((0..200).map(ready)).buffered_unordered_adaptive(aimd, |_| true);
+        let queue = |buffered: &Adaptive<BufferUnordered<_>, _>| {
+            #[cfg(feature = "metrics")]
+            assert_eq!(
+                buffered.stream.in_progress_queue.metrics().allocated_bytes,
+                FuturesUnorderedBounded::<core::future::Ready<i32>>::new(64)
+                    .metrics()
+                    .allocated_bytes
+            );
+            buffered.stream.in_progress_queue.tasks.allocated()
+        };
+        assert_eq!(queue(&buffered), 64);
+
+        while futures::executor::block_on(buffered.next()).is_some() {
+            assert_eq!(queue(&buffered), 64);
+        }
+        assert_eq!(buffered.limit(), 64);
+    }
+
+    #[test]
+    fn try_buffered_unordered_adaptive() {
+        let aimd = Aimd::new(1, 8).initial(8);
+        let mut buffered = stream::iter((0..4).map(|i| Ok(ready(Err::<(), _>(i)))))
+            .try_buffered_unordered_adaptive(aimd);
+        assert_eq!(buffered.limit(), 8);
+
+        let mut limits = vec![];
+        while futures::executor::block_on(buffered.next()).is_some() {
+            limits.push(buffered.limit());
+        }
+        assert_eq!(limits, [4, 2, 1, 1]);
+    }
+
+    #[test]
+    fn try_buffered_unordered_adaptive_by() {
+        let aimd = Aimd::new(1, 8).initial(8);
+        let mut buffered = stream::iter((0..4).map(|i| Ok(ready(Err::<(), _>(i)))))
+            .try_buffered_unordered_adaptive_by(aimd, |res| res != &Err(2));
+        assert_eq!(buffered.limit(), 8);
+
+        let mut limits = vec![];
+        while futures::executor::block_on(buffered.next()).is_some() {
+            limits.push(buffered.limit());
+        }
+        assert_eq!(limits, [8, 8, 4, 5]);
+    }
+}
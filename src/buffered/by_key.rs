@@ -0,0 +1,180 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{
+    keyed::{KeyedQueues, Tagged},
+    FuturesUnorderedBounded, PollBudget,
+};
+
+pin_project!(
+    /// Stream for the [`buffered_ordered_by_key`](crate::BufferedStreamExt::buffered_ordered_by_key)
+    /// method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct BufferedOrderedByKey<S: Stream, K, KF> {
+        #[pin]
+        stream: Option<S>,
+        key_fn: KF,
+        in_progress_queue: FuturesUnorderedBounded<Tagged<K, S::Item>>,
+        // futures waiting for an earlier future with the same key to complete
+        queued: KeyedQueues<K, S::Item>,
+    }
+);
+
+impl<S: Stream, K: Ord, KF> BufferedOrderedByKey<S, K, KF> {
+    pub(crate) fn new(stream: S, n: usize, key_fn: KF) -> Self {
+        Self {
+            stream: Some(stream),
+            key_fn,
+            in_progress_queue: FuturesUnorderedBounded::new(n),
+            queued: KeyedQueues::new(),
+        }
+    }
+
+    /// Returns the number of futures waiting for an earlier future with the same key.
+    pub fn waiting(&self) -> usize {
+        self.queued.waiting()
+    }
+
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.in_progress_queue.set_poll_budget(budget);
+        self
+    }
+}
+
+impl<St, K, KF> Stream for BufferedOrderedByKey<St, K, KF>
+where
+    St: Stream,
+    St::Item: Future,
+    K: Ord + Clone,
+    KF: FnMut(&St::Item) -> K,
+{
+    type Item = <St::Item as Future>::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        // First up, try to spawn off as many futures as possible by filling up
+        // our queue of futures. Futures for a busy key wait their turn,
+        // up to as many as could be in-flight.
+        let unordered = this.in_progress_queue;
+        while unordered.len() < unordered.capacity() && this.queued.waiting() < unordered.capacity()
+        {
+            if let Some(s) = this.stream.as_mut().as_pin_mut() {
+                match s.poll_next(cx) {
+                    Poll::Ready(Some(fut)) => {
+                        let key = (this.key_fn)(&fut);
+                        if let Some(fut) = this.queued.acquire(key.clone(), fut) {
+                            unordered.push(Tagged::new(key, fut));
+                        }
+                        continue;
+                    }
+                    Poll::Ready(None) => this.stream.as_mut().set(None),
+                    Poll::Pending => {}
+                }
+            }
+            break;
+        }
+
+        // Attempt to pull the next value from the in_progress_queue,
+        // and start the next future for the same key
+        match unordered.poll_inner(cx) {
+            Poll::Ready(Some((_, (key, output)))) => {
+                if let Some(fut) = this.queued.release(&key) {
+                    unordered.push(Tagged::new(key, fut));
+                }
+                return Poll::Ready(Some(output));
+            }
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => {}
+        }
+
+        // If more values are still coming from the stream, we're not done yet
+        if this.stream.as_pin_mut().is_none() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let queue_len = self.in_progress_queue.len() + self.queued.waiting();
+        match &self.stream {
+            Some(s) => {
+                let (lower, upper) = s.size_hint();
+                let lower = lower.saturating_add(queue_len);
+                let upper = match upper {
+                    Some(x) => x.checked_add(queue_len),
+                    None => None,
+                };
+                (lower, upper)
+            }
+            _ => (queue_len, Some(queue_len)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BufferedStreamExt;
+    use futures::{channel::oneshot, stream, StreamExt};
+    use futures_test::task::noop_context;
+
+    struct Job {
+        key: char,
+        rx: oneshot::Receiver<usize>,
+    }
+
+    impl Future for Job {
+        type Output = (char, usize);
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let key = self.key;
+            Pin::new(&mut self.rx).poll(cx).map(|x| (key, x.unwrap()))
+        }
+    }
+
+    #[test]
+    fn buffered_ordered_by_key() {
+        let keys = ['a', 'b', 'a', 'c', 'a'];
+        let (senders, jobs): (Vec<_>, Vec<_>) = keys
+            .iter()
+            .map(|&key| {
+                let (tx, rx) = oneshot::channel();
+                (tx, Job { key, rx })
+            })
+            .unzip();
+
+        let mut buffered = stream::iter(jobs).buffered_ordered_by_key(3, |job: &Job| job.key);
+        let mut cx = noop_context();
+
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        // 'a', 'b' and 'c' are running, while the second 'a' is waiting
+        assert_eq!(buffered.in_progress_queue.len(), 3);
+        assert_eq!(buffered.waiting(), 1);
+        assert_eq!(buffered.size_hint(), (5, Some(5)));
+
+        // complete the jobs in reverse, which only completes them once they run
+        for (i, tx) in senders.into_iter().enumerate().rev() {
+            tx.send(i).unwrap();
+        }
+
+        let mut outputs = vec![];
+        while let Poll::Ready(Some(x)) = buffered.poll_next_unpin(&mut cx) {
+            outputs.push(x);
+        }
+        assert_eq!(outputs.len(), 5);
+
+        // outputs for the same key stay in order
+        let a: Vec<_> = outputs.iter().filter(|(k, _)| *k == 'a').collect();
+        assert_eq!(a, [&('a', 0), &('a', 2), &('a', 4)]);
+    }
+}
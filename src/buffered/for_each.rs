@@ -6,51 +6,66 @@ use core::{
 use futures_core::{FusedFuture, Stream};
 use pin_project_lite::pin_project;
 
-use crate::FuturesUnorderedBounded;
+use crate::{
+    instrument::{slot_span, MakeSpan, NoSpan},
+    FuturesUnorderedBounded, PollBudget,
+};
 
 pin_project! {
     /// Future for the [`for_each_concurrent`](super::StreamExt::for_each_concurrent)
     /// method.
     #[must_use = "futures do nothing unless you `.await` or poll them"]
-    pub struct ForEachConcurrent<St, Fut, F> {
+    pub struct ForEachConcurrent<St, Fut, F, SF = NoSpan> {
         #[pin]
         stream: Option<St>,
         f: F,
+        span_fn: SF,
         futures: FuturesUnorderedBounded<Fut>,
     }
 }
 
-impl<St, Fut, F> ForEachConcurrent<St, Fut, F>
+impl<St, Fut, F, SF> ForEachConcurrent<St, Fut, F, SF>
 where
     St: Stream,
     F: FnMut(St::Item) -> Fut,
     Fut: Future<Output = ()>,
 {
-    pub(super) fn new(stream: St, limit: usize, f: F) -> Self {
+    pub(super) fn new(stream: St, limit: usize, f: F, span_fn: SF) -> Self {
         Self {
             stream: Some(stream),
             f,
+            span_fn,
             futures: FuturesUnorderedBounded::new(limit),
         }
     }
+
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.futures.set_poll_budget(budget);
+        self
+    }
 }
 
-impl<St, Fut, F> FusedFuture for ForEachConcurrent<St, Fut, F>
+impl<St, Fut, F, SF> FusedFuture for ForEachConcurrent<St, Fut, F, SF>
 where
     St: Stream,
     F: FnMut(St::Item) -> Fut,
     Fut: Future<Output = ()>,
+    SF: MakeSpan<Fut>,
 {
     fn is_terminated(&self) -> bool {
         self.stream.is_none() && self.futures.is_empty()
     }
 }
 
-impl<St, Fut, F> Future for ForEachConcurrent<St, Fut, F>
+impl<St, Fut, F, SF> Future for ForEachConcurrent<St, Fut, F, SF>
 where
     St: Stream,
     F: FnMut(St::Item) -> Fut,
     Fut: Future<Output = ()>,
+    SF: MakeSpan<Fut>,
 {
     type Output = ();
 
@@ -68,7 +83,9 @@ where
                     match s.poll_next(cx) {
                         Poll::Ready(Some(elem)) => {
                             should_poll_stream = true;
-                            unordered.push((this.f)(elem));
+                            let fut = (this.f)(elem);
+                            let span = slot_span(this.span_fn, &fut);
+                            unordered.push_spanned(fut, span);
                         }
                         Poll::Ready(None) => this.stream.as_mut().set(None),
                         Poll::Pending => {}
@@ -0,0 +1,220 @@
+use alloc::sync::Arc;
+use core::{
+    fmt, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+    task::Context,
+};
+use futures_util::task::AtomicWaker;
+
+/// A shared concurrency limit that can be changed while a stream is being polled.
+///
+/// Cloning the limit produces a handle to the same shared value, so it can be passed to
+/// [`buffered_unordered_with_limit`](crate::BufferedStreamExt::buffered_unordered_with_limit)
+/// or [`buffered_ordered_with_limit`](crate::BufferedStreamExt::buffered_ordered_with_limit)
+/// and then adjusted from elsewhere.
+///
+/// Changing the limit wakes every task polling a stream that uses it, so raising the limit
+/// will start new futures even if the stream was otherwise idle. Lowering the limit will not
+/// cancel any in-flight futures, instead no new futures will be started until enough of them
+/// complete.
+///
+/// # Examples
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::stream::{self, StreamExt};
+/// use futures_buffered::{BufferedStreamExt, ConcurrencyLimit};
+/// use std::future::ready;
+///
+/// let limit = ConcurrencyLimit::new(2);
+/// let mut buffered = stream::iter((0..10).map(ready)).buffered_unordered_with_limit(limit.clone());
+///
+/// assert!(buffered.next().await.is_some());
+/// limit.set(8);
+/// assert_eq!(buffered.count().await, 9);
+/// # })
+/// ```
+#[derive(Clone)]
+pub struct ConcurrencyLimit(Arc<Shared>);
+
+struct Shared {
+    limit: AtomicUsize,
+    // a lock-free list of the streams using this limit. Nodes are only ever pushed, and are
+    // reused once their stream is dropped, so the list only grows to the largest number of
+    // streams that used the limit at the same time. Each node owns one of the `Arc`'s strong counts.
+    listeners: AtomicPtr<Listener>,
+}
+
+struct Listener {
+    waker: AtomicWaker,
+    // set while a stream is using this node
+    active: AtomicBool,
+    // never changed once the node is in the list
+    next: AtomicPtr<Listener>,
+}
+
+impl Shared {
+    fn listeners(&self) -> impl Iterator<Item = &Listener> {
+        let mut ptr = self.listeners.load(Ordering::Acquire);
+        core::iter::from_fn(move || {
+            // SAFETY: nodes are never removed from the list, and are only freed once the list is dropped
+            let node = unsafe { ptr.as_ref()? };
+            ptr = node.next.load(Ordering::Acquire);
+            Some(node)
+        })
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let mut ptr = *self.listeners.get_mut();
+        while !ptr.is_null() {
+            // SAFETY: every node in the list was created by `Arc::into_raw`, and is released only once
+            let node = unsafe { Arc::from_raw(ptr) };
+            ptr = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+impl ConcurrencyLimit {
+    /// Creates a new shared limit with the given initial value.
+    pub fn new(limit: usize) -> Self {
+        Self(Arc::new(Shared {
+            limit: AtomicUsize::new(limit),
+            listeners: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+
+    /// Returns the current limit.
+    pub fn get(&self) -> usize {
+        self.0.limit.load(Ordering::Relaxed)
+    }
+
+    /// Changes the limit for every stream that shares it, and wakes them
+    /// so that the new limit takes effect.
+    pub fn set(&self, limit: usize) {
+        self.store(limit);
+        for listener in self.0.listeners() {
+            if listener.active.load(Ordering::Acquire) {
+                listener.waker.wake();
+            }
+        }
+    }
+
+    /// Changes the limit without waking any of the streams.
+    ///
+    /// Only for callers that will poll the stream again anyway.
+    pub(crate) fn store(&self, limit: usize) {
+        self.0.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Creates a handle for a stream to follow the limit, which gets woken by [`ConcurrencyLimit::set`].
+    pub(crate) fn listen(&self) -> LimitListener {
+        let reused = self.0.listeners().find(|listener| {
+            listener
+                .active
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        });
+        let listener = match reused {
+            // SAFETY: the node came from `Arc::into_raw`, and the list keeps its count alive
+            Some(listener) => unsafe {
+                let ptr: *const Listener = listener;
+                Arc::increment_strong_count(ptr);
+                Arc::from_raw(ptr)
+            },
+            None => {
+                let listener = Arc::new(Listener {
+                    waker: AtomicWaker::new(),
+                    active: AtomicBool::new(true),
+                    next: AtomicPtr::new(ptr::null_mut()),
+                });
+                let node = Arc::into_raw(listener.clone()).cast_mut();
+                let mut head = self.0.listeners.load(Ordering::Relaxed);
+                loop {
+                    listener.next.store(head, Ordering::Relaxed);
+                    match self.0.listeners.compare_exchange_weak(
+                        head,
+                        node,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => break,
+                        Err(current) => head = current,
+                    }
+                }
+                listener
+            }
+        };
+        LimitListener {
+            limit: self.clone(),
+            listener,
+        }
+    }
+}
+
+impl fmt::Debug for ConcurrencyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConcurrencyLimit")
+            .field(&self.get())
+            .finish()
+    }
+}
+
+/// A stream's handle to a [`ConcurrencyLimit`].
+pub(crate) struct LimitListener {
+    limit: ConcurrencyLimit,
+    listener: Arc<Listener>,
+}
+
+impl LimitListener {
+    /// Returns the current limit, and registers the task to be woken when it changes.
+    pub(crate) fn poll_get(&self, cx: &mut Context<'_>) -> usize {
+        self.listener.waker.register(cx.waker());
+        self.limit.get()
+    }
+}
+
+impl Drop for LimitListener {
+    fn drop(&mut self) {
+        // let another stream reuse the node
+        self.listener.active.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::ArcWake;
+    use std::sync::atomic::AtomicUsize;
+
+    struct CountWakes(AtomicUsize);
+    impl ArcWake for CountWakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn listeners() {
+        let limit = ConcurrencyLimit::new(1);
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = futures::task::waker(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let a = limit.listen();
+        let b = limit.listen();
+        assert_eq!(a.poll_get(&mut cx), 1);
+        assert_eq!(b.poll_get(&mut cx), 1);
+        limit.set(2);
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 2);
+
+        // dropped listeners are not woken, and their nodes are reused
+        drop(a);
+        assert_eq!(b.poll_get(&mut cx), 2);
+        limit.set(3);
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 3);
+        let _c = limit.listen();
+        assert_eq!(limit.0.listeners().count(), 2);
+    }
+}
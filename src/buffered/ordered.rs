@@ -1,4 +1,7 @@
-use crate::FuturesOrderedBounded;
+use super::LimitListener;
+use crate::{
+    head_of_line::HeadState, FuturesOrderedBounded, HeadPolicy, PollBudget, Stalled, Timer,
+};
 use core::{
     future::Future,
     pin::Pin,
@@ -18,23 +21,67 @@ pin_project! {
         #[pin]
         pub(crate) stream: Option<St>,
         pub(crate) in_progress_queue: FuturesOrderedBounded<St::Item>,
+        pub(crate) limit: Option<LimitListener>,
     }
 }
 
-impl<St> Stream for BufferedOrdered<St>
+impl<St> BufferedOrdered<St>
 where
     St: Stream,
     St::Item: Future,
 {
-    type Item = <St::Item as Future>::Output;
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.in_progress_queue.set_poll_budget(budget);
+        self
+    }
+}
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+impl<St> BufferedOrdered<St>
+where
+    St: Stream,
+    St::Item: Future,
+{
+    /// Gives up on a head future that holds up the outputs behind it, according to `policy`.
+    ///
+    /// Each output is wrapped in `Ok`. Once the [`HeadPolicy`] gives up on the head future,
+    /// it is dropped and, depending on the [`HeadAction`](crate::HeadAction), either skipped
+    /// or replaced by `Err(Stalled)`.
+    ///
+    /// This keeps the concurrency limit and reorder window of the stream.
+    pub fn with_head_policy<T: Timer>(
+        self,
+        policy: HeadPolicy<T>,
+    ) -> BufferedOrderedHeadOfLine<St, T> {
+        BufferedOrderedHeadOfLine {
+            inner: self,
+            head: HeadState::new(policy),
+        }
+    }
+
+    /// Starts as many futures as the limit allows, then polls the queue with `poll_queue`.
+    fn poll_with<R>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        poll_queue: impl FnOnce(
+            &mut FuturesOrderedBounded<St::Item>,
+            &mut Context<'_>,
+        ) -> Poll<Option<R>>,
+    ) -> Poll<Option<R>> {
         let mut this = self.project();
 
         // First up, try to spawn off as many futures as possible by filling up
         // our queue of futures.
         let ordered = this.in_progress_queue;
-        while ordered.in_progress_queue.tasks.len() < ordered.in_progress_queue.tasks.capacity() {
+        if let Some(limit) = this.limit {
+            let limit = limit.poll_get(cx);
+            if limit != ordered.in_progress_queue.capacity() {
+                ordered.in_progress_queue.set_capacity(limit);
+            }
+        }
+        while ordered.has_room() {
             if let Some(s) = this.stream.as_mut().as_pin_mut() {
                 match s.poll_next(cx) {
                     Poll::Ready(Some(fut)) => {
@@ -49,8 +96,7 @@ where
         }
 
         // Attempt to pull the next value from the in_progress_queue
-        let res = Pin::new(ordered).poll_next(cx);
-        if let Some(val) = ready!(res) {
+        if let Some(val) = ready!(poll_queue(ordered, cx)) {
             return Poll::Ready(Some(val));
         }
 
@@ -61,6 +107,18 @@ where
             Poll::Pending
         }
     }
+}
+
+impl<St> Stream for BufferedOrdered<St>
+where
+    St: Stream,
+    St::Item: Future,
+{
+    type Item = <St::Item as Future>::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_with(cx, |ordered, cx| Pin::new(ordered).poll_next(cx))
+    }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         match &self.stream {
@@ -79,9 +137,61 @@ where
     }
 }
 
+pin_project! {
+    /// Stream for the [`buffered_ordered_with_head_policy`](crate::BufferedStreamExt::buffered_ordered_with_head_policy)
+    /// and [`BufferedOrdered::with_head_policy`] methods.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct BufferedOrderedHeadOfLine<St, T>
+    where
+        St: Stream,
+        St::Item: Future,
+        T: Timer,
+    {
+        #[pin]
+        pub(crate) inner: BufferedOrdered<St>,
+        #[pin]
+        pub(crate) head: HeadState<T>,
+    }
+}
+
+impl<St, T> BufferedOrderedHeadOfLine<St, T>
+where
+    St: Stream,
+    St::Item: Future,
+    T: Timer,
+{
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.inner = self.inner.with_poll_budget(budget);
+        self
+    }
+}
+
+impl<St, T> Stream for BufferedOrderedHeadOfLine<St, T>
+where
+    St: Stream,
+    St::Item: Future,
+    T: Timer,
+{
+    type Item = Result<<St::Item as Future>::Output, Stalled>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let head = this.head;
+        this.inner
+            .poll_with(cx, |ordered, cx| head.poll_next(ordered, cx))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::BufferedStreamExt;
+    use crate::{BufferedStreamExt, ConcurrencyLimit};
 
     use super::*;
     use futures::{channel::oneshot, stream, StreamExt};
@@ -119,4 +229,113 @@ mod tests {
         // completes properly
         assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
     }
+
+    #[test]
+    fn buffered_ordered_with_limit() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..3).map(|_| oneshot::channel()).unzip();
+
+        let limit = ConcurrencyLimit::new(1);
+        let mut buffered = stream::iter(receivers).buffered_ordered_with_limit(limit.clone());
+        let mut cx = noop_context();
+
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 1);
+
+        limit.set(3);
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 3);
+
+        for (i, tx) in senders.into_iter().enumerate().rev() {
+            tx.send(i).unwrap();
+        }
+        for i in 0..3 {
+            assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(i))));
+        }
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn buffered_ordered_with_window() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+
+        let mut buffered = stream::iter(receivers).buffered_ordered_with_window(4, 2);
+        let mut cx = noop_context();
+
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 2);
+
+        let mut senders = senders.into_iter();
+        let first = senders.next().unwrap();
+        senders.next().unwrap().send(1).unwrap();
+
+        // the completed output still holds its place in the window
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 2);
+
+        first.send(0).unwrap();
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(0))));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(1))));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 2);
+    }
+
+    #[test]
+    fn buffered_ordered_with_head_policy() {
+        use crate::{timer::mock::MockTimer, HeadAction, HeadPolicy};
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+
+        let policy = HeadPolicy::new(HeadAction::Skip, MockTimer::default()).successors(1);
+        let buffered = stream::iter(receivers).buffered_ordered_with_head_policy(2, policy);
+        let mut buffered = core::pin::pin!(buffered);
+        let mut cx = noop_context();
+
+        let mut senders = senders.into_iter();
+        let _stuck = senders.next().unwrap();
+        assert_eq!(buffered.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        // the stuck head is skipped as soon as the next future completes
+        for (i, tx) in senders.enumerate() {
+            tx.send(i + 1).unwrap();
+        }
+        for i in 1..4 {
+            assert_eq!(
+                buffered.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some(Ok(Ok(i))))
+            );
+        }
+        assert_eq!(buffered.as_mut().poll_next(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn head_policy_with_window() {
+        use crate::{timer::mock::MockTimer, HeadAction, HeadPolicy};
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+
+        let policy = HeadPolicy::new(HeadAction::Skip, MockTimer::default()).successors(1);
+        let buffered = stream::iter(receivers)
+            .buffered_ordered_with_window(4, 2)
+            .with_head_policy(policy);
+        let mut buffered = core::pin::pin!(buffered);
+        let mut cx = noop_context();
+
+        // the window still limits how many futures are started
+        assert_eq!(buffered.as_mut().poll_next(&mut cx), Poll::Pending);
+        assert_eq!(buffered.inner.in_progress_queue.len(), 2);
+
+        let mut senders = senders.into_iter();
+        let _stuck = senders.next().unwrap();
+        for (i, tx) in senders.enumerate() {
+            tx.send(i + 1).unwrap();
+        }
+        for i in 1..4 {
+            assert_eq!(
+                buffered.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some(Ok(Ok(i))))
+            );
+            assert!(buffered.inner.in_progress_queue.len() <= 2);
+        }
+        assert_eq!(buffered.as_mut().poll_next(&mut cx), Poll::Ready(None));
+    }
 }
@@ -6,7 +6,11 @@ use core::{
 use futures_core::Stream;
 use pin_project_lite::pin_project;
 
-use crate::FuturesUnorderedBounded;
+use super::LimitListener;
+use crate::{
+    instrument::{slot_span, MakeSpan, NoSpan},
+    FuturesUnorderedBounded, PollBudget,
+};
 
 pin_project!(
     /// Stream for the [`buffered_unordered`](crate::BufferedStreamExt::buffered_unordered)
@@ -67,17 +71,30 @@ pin_project!(
     ///     dealloc:  0 B
     /// ```
     #[must_use = "streams do nothing unless polled"]
-    pub struct BufferUnordered<S: Stream> {
+    pub struct BufferUnordered<S: Stream, SF = NoSpan> {
         #[pin]
         pub(crate) stream: Option<S>,
         pub(crate) in_progress_queue: FuturesUnorderedBounded<S::Item>,
+        pub(crate) limit: Option<LimitListener>,
+        pub(crate) span_fn: SF,
     }
 );
 
-impl<St> Stream for BufferUnordered<St>
+impl<St: Stream, SF> BufferUnordered<St, SF> {
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.in_progress_queue.set_poll_budget(budget);
+        self
+    }
+}
+
+impl<St, SF> Stream for BufferUnordered<St, SF>
 where
     St: Stream,
     St::Item: Future,
+    SF: MakeSpan<St::Item>,
 {
     type Item = <St::Item as Future>::Output;
 
@@ -87,11 +104,18 @@ where
         // First up, try to spawn off as many futures as possible by filling up
         // our queue of futures.
         let unordered = this.in_progress_queue;
+        if let Some(limit) = this.limit {
+            let limit = limit.poll_get(cx);
+            if limit != unordered.capacity() {
+                unordered.set_capacity(limit);
+            }
+        }
         while unordered.tasks.len() < unordered.tasks.capacity() {
             if let Some(s) = this.stream.as_mut().as_pin_mut() {
                 match s.poll_next(cx) {
                     Poll::Ready(Some(fut)) => {
-                        unordered.push(fut);
+                        let span = slot_span(this.span_fn, &fut);
+                        unordered.push_spanned(fut, span);
                         continue;
                     }
                     Poll::Ready(None) => this.stream.as_mut().set(None),
@@ -134,11 +158,12 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::BufferedStreamExt;
+    use crate::{BufferedStreamExt, ConcurrencyLimit};
 
     use super::*;
     use futures::{channel::oneshot, stream, StreamExt};
-    use futures_test::task::noop_context;
+    use futures_test::task::{new_count_waker, noop_context};
+    use std::future::ready;
 
     #[test]
     fn buffered_unordered() {
@@ -171,4 +196,70 @@ mod tests {
         // completes properly
         assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
     }
+
+    #[test]
+    fn buffered_unordered_with_limit() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+
+        let limit = ConcurrencyLimit::new(1);
+        let mut buffered = stream::iter(receivers).buffered_unordered_with_limit(limit.clone());
+        let mut cx = noop_context();
+
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 1);
+
+        limit.set(3);
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_progress_queue.len(), 3);
+
+        // lowering the limit keeps the in-flight futures
+        limit.set(1);
+        for (i, tx) in senders.into_iter().enumerate() {
+            tx.send(i).unwrap();
+        }
+        for _ in 0..3 {
+            assert!(matches!(
+                buffered.poll_next_unpin(&mut cx),
+                Poll::Ready(Some(Ok(_)))
+            ));
+            assert!(buffered.in_progress_queue.len() <= 2);
+        }
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(3))));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn raising_limit_wakes() {
+        let (waker, wakes) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let limit = ConcurrencyLimit::new(0);
+        let mut buffered =
+            stream::iter((0..2).map(ready)).buffered_unordered_with_limit(limit.clone());
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(wakes.get(), 0);
+
+        // nothing else would ever wake the stream
+        limit.set(1);
+        assert_eq!(wakes.get(), 1);
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(0)));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn buffered_unordered_with_span() {
+        use std::cell::Cell;
+
+        // the span fn borrows local state, so it is neither `Sync` nor `'static`
+        let spans = Cell::new(0);
+        let buffered = stream::iter([ready(1), ready(2)]).buffered_unordered_with_span(2, |_| {
+            spans.set(spans.get() + 1);
+            tracing::info_span!("item")
+        });
+
+        assert_eq!(futures::executor::block_on(buffered.count()), 2);
+        assert_eq!(spans.get(), 2);
+    }
 }
@@ -0,0 +1,211 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+
+use crate::{FuturesUnordered, PollBudget};
+
+pin_project!(
+    struct Weighted<F> {
+        #[pin]
+        future: F,
+        weight: usize,
+    }
+);
+
+impl<F: Future> Future for Weighted<F> {
+    type Output = (usize, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.future.poll(cx));
+        Poll::Ready((*this.weight, output))
+    }
+}
+
+pin_project!(
+    /// Stream for the [`buffered_unordered_weighted`](crate::BufferedStreamExt::buffered_unordered_weighted)
+    /// method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct BufferUnorderedWeighted<S: Stream, W> {
+        #[pin]
+        stream: Option<S>,
+        weight_fn: W,
+        // an item taken from the stream that did not fit in the remaining budget
+        pending: Option<(S::Item, usize)>,
+        in_progress_queue: FuturesUnordered<Weighted<S::Item>>,
+        in_flight_weight: usize,
+        budget: usize,
+    }
+);
+
+impl<S: Stream, W> BufferUnorderedWeighted<S, W> {
+    pub(crate) fn new(stream: S, budget: usize, weight_fn: W) -> Self {
+        Self {
+            stream: Some(stream),
+            weight_fn,
+            pending: None,
+            in_progress_queue: FuturesUnordered::new(),
+            in_flight_weight: 0,
+            budget,
+        }
+    }
+
+    /// Returns the summed weight of all in-flight futures.
+    pub fn in_flight_weight(&self) -> usize {
+        self.in_flight_weight
+    }
+
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.in_progress_queue.set_poll_budget(budget);
+        self
+    }
+}
+
+impl<St, W> Stream for BufferUnorderedWeighted<St, W>
+where
+    St: Stream,
+    St::Item: Future,
+    W: FnMut(&St::Item) -> usize,
+{
+    type Item = <St::Item as Future>::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        // First up, try to spawn off as many futures as fit into the budget.
+        loop {
+            let (fut, weight) = match this.pending.take() {
+                Some(pending) => pending,
+                None => match this.stream.as_mut().as_pin_mut() {
+                    Some(s) => match s.poll_next(cx) {
+                        Poll::Ready(Some(fut)) => {
+                            let weight = (this.weight_fn)(&fut);
+                            (fut, weight)
+                        }
+                        Poll::Ready(None) => {
+                            this.stream.as_mut().set(None);
+                            break;
+                        }
+                        Poll::Pending => break,
+                    },
+                    None => break,
+                },
+            };
+
+            // always admit a future if nothing else is running,
+            // otherwise an item heavier than the budget could never start.
+            let fits = this
+                .in_flight_weight
+                .checked_add(weight)
+                .is_some_and(|w| w <= *this.budget);
+            if fits || this.in_progress_queue.is_empty() {
+                *this.in_flight_weight = this.in_flight_weight.saturating_add(weight);
+                this.in_progress_queue.push(Weighted {
+                    future: fut,
+                    weight,
+                });
+            } else {
+                *this.pending = Some((fut, weight));
+                break;
+            }
+        }
+
+        // Attempt to pull the next value from the in_progress_queue
+        match Pin::new(this.in_progress_queue).poll_next(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some((weight, output))) => {
+                *this.in_flight_weight -= weight;
+                return Poll::Ready(Some(output));
+            }
+            Poll::Ready(None) => {}
+        }
+
+        // If more values are still coming from the stream, we're not done yet
+        if this.stream.as_pin_mut().is_none() && this.pending.is_none() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let queue_len = self.in_progress_queue.len() + usize::from(self.pending.is_some());
+        match &self.stream {
+            Some(s) => {
+                let (lower, upper) = s.size_hint();
+                let lower = lower.saturating_add(queue_len);
+                let upper = match upper {
+                    Some(x) => x.checked_add(queue_len),
+                    None => None,
+                };
+                (lower, upper)
+            }
+            _ => (queue_len, Some(queue_len)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BufferedStreamExt;
+
+    use super::*;
+    use futures::{channel::oneshot, stream, FutureExt, StreamExt};
+    use futures_test::task::noop_context;
+
+    struct Job {
+        weight: usize,
+        rx: oneshot::Receiver<usize>,
+    }
+
+    impl Future for Job {
+        type Output = usize;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.rx.poll_unpin(cx).map(Result::unwrap)
+        }
+    }
+
+    #[test]
+    fn buffered_unordered_weighted() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+        let jobs = [6, 4, 3, 20]
+            .into_iter()
+            .zip(receivers)
+            .map(|(weight, rx)| Job { weight, rx });
+
+        let mut buffered = stream::iter(jobs).buffered_unordered_weighted(10, |job| job.weight);
+        let mut cx = noop_context();
+        let mut senders = senders.into_iter();
+
+        // 6 + 4 fit in the budget, but 3 more does not
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_flight_weight(), 10);
+        assert_eq!(buffered.size_hint(), (4, Some(4)));
+
+        senders.next().unwrap().send(0).unwrap();
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(0)));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_flight_weight(), 7);
+
+        senders.next().unwrap().send(1).unwrap();
+        senders.next().unwrap().send(2).unwrap();
+        assert!(buffered.poll_next_unpin(&mut cx).is_ready());
+        assert!(buffered.poll_next_unpin(&mut cx).is_ready());
+
+        // too heavy for the budget, but admitted on its own
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffered.in_flight_weight(), 20);
+
+        senders.next().unwrap().send(3).unwrap();
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(3)));
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
+}
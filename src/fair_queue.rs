@@ -0,0 +1,275 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::{FusedStream, Stream};
+
+use crate::{keyed::Tagged, FuturesUnorderedBounded};
+
+/// A bounded set of futures shared fairly between keys, such as tenants.
+///
+/// Up to `capacity` futures run at once, like in [`FuturesUnorderedBounded`]. Futures pushed
+/// while the set is full wait in a FIFO queue for their key. Whenever a running future
+/// completes, the free slot is handed to the next key in turn, so one busy key
+/// cannot starve the rest.
+///
+/// By default every key gets one slot per turn (round-robin). Keys can be given a larger
+/// [`weight`](FairQueue::set_weight), in which case they get that many slots per turn
+/// (weighted fair queuing).
+///
+/// Outputs are returned alongside their key, in the order in which they complete.
+///
+/// # Example
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::StreamExt;
+/// use futures_buffered::FairQueue;
+/// use std::future::ready;
+///
+/// let mut queue = FairQueue::new(1);
+/// queue.push("noisy", ready(1));
+/// queue.push("noisy", ready(2));
+/// queue.push("noisy", ready(3));
+/// queue.push("quiet", ready(4));
+///
+/// let outputs: Vec<_> = queue.collect().await;
+/// assert_eq!(outputs, [("noisy", 1), ("noisy", 2), ("quiet", 4), ("noisy", 3)]);
+/// # });
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct FairQueue<K, F> {
+    in_progress_queue: FuturesUnorderedBounded<Tagged<K, F>>,
+    // the waiting futures for each key, which are removed once empty
+    queues: BTreeMap<K, VecDeque<F>>,
+    weights: BTreeMap<K, u32>,
+    // the keys with waiting futures, in the order they take their turn
+    active: VecDeque<K>,
+    // the slots left in the turn of the key at the front of `active`
+    deficit: u32,
+    pending: usize,
+}
+
+impl<K, F> Unpin for FairQueue<K, F> {}
+
+impl<K: Ord + Clone, F> FairQueue<K, F> {
+    /// Constructs a new, empty `FairQueue` which runs up to `capacity` futures at once.
+    ///
+    /// The returned queue does not contain any futures.
+    /// In this state, [`FairQueue::poll_next`](Stream::poll_next) will
+    /// return [`Poll::Ready(None)`](Poll::Ready).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            in_progress_queue: FuturesUnorderedBounded::new(capacity),
+            queues: BTreeMap::new(),
+            weights: BTreeMap::new(),
+            active: VecDeque::new(),
+            deficit: 0,
+            pending: 0,
+        }
+    }
+
+    /// Sets how many slots `key` is handed per turn. The default weight is 1.
+    ///
+    /// # Panics
+    /// This method will panic if `weight` is 0
+    #[track_caller]
+    pub fn set_weight(&mut self, key: K, weight: u32) {
+        assert!(weight > 0, "the weight of a key must be at least 1");
+        if weight == 1 {
+            self.weights.remove(&key);
+        } else {
+            self.weights.insert(key, weight);
+        }
+    }
+
+    /// Push a future into the queue for the given key.
+    ///
+    /// The future is started straight away if fewer than `capacity` futures are running
+    /// and no other futures are waiting.
+    ///
+    /// This function will not call `poll` on the submitted future. The caller
+    /// must ensure that [`FairQueue::poll_next`](Stream::poll_next) is called
+    /// in order to receive wake-up notifications for the given future.
+    pub fn push(&mut self, key: K, fut: F) {
+        if self.pending == 0 && self.in_progress_queue.len() < self.in_progress_queue.capacity() {
+            self.in_progress_queue.push(Tagged::new(key, fut));
+            return;
+        }
+
+        self.pending += 1;
+        match self.queues.get_mut(&key) {
+            Some(queue) => queue.push_back(fut),
+            None => {
+                self.queues.insert(key.clone(), VecDeque::from([fut]));
+                self.active.push_back(key);
+            }
+        }
+    }
+
+    /// Returns `true` if the queue contains no futures
+    pub fn is_empty(&self) -> bool {
+        self.in_progress_queue.is_empty() && self.pending == 0
+    }
+
+    /// Returns the number of futures contained in the queue, both running and waiting.
+    pub fn len(&self) -> usize {
+        self.in_progress_queue.len() + self.pending
+    }
+
+    /// Returns the number of futures waiting to be started.
+    pub fn pending_len(&self) -> usize {
+        self.pending
+    }
+
+    /// Returns the number of futures waiting to be started for the given key.
+    pub fn pending_len_for(&self, key: &K) -> usize {
+        self.queues.get(key).map_or(0, VecDeque::len)
+    }
+
+    /// Returns the number of futures that can run at once.
+    pub fn capacity(&self) -> usize {
+        self.in_progress_queue.capacity()
+    }
+
+    /// Hands out the free slots to the waiting keys in turn.
+    fn admit(&mut self) {
+        while self.in_progress_queue.len() < self.in_progress_queue.capacity() {
+            let Some(key) = self.active.front() else {
+                break;
+            };
+            if self.deficit == 0 {
+                self.deficit = self.weights.get(key).copied().unwrap_or(1);
+            }
+
+            let queue = self
+                .queues
+                .get_mut(key)
+                .expect("active keys should have waiting futures");
+            let fut = queue
+                .pop_front()
+                .expect("waiting queues should not be empty");
+            self.pending -= 1;
+            self.deficit -= 1;
+
+            let key = if queue.is_empty() {
+                // the key has nothing left to run, so it leaves the rotation
+                self.deficit = 0;
+                let key = self.active.pop_front().expect("key should be active");
+                self.queues.remove(&key);
+                key
+            } else if self.deficit == 0 {
+                // the key's turn is over
+                let key = self.active.pop_front().expect("key should be active");
+                self.active.push_back(key.clone());
+                key
+            } else {
+                key.clone()
+            };
+
+            self.in_progress_queue.push(Tagged::new(key, fut));
+        }
+    }
+}
+
+impl<K: Ord + Clone, F: Future> Stream for FairQueue<K, F> {
+    type Item = (K, F::Output);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        this.admit();
+        match this.in_progress_queue.poll_inner(cx) {
+            Poll::Ready(Some((_, output))) => {
+                // a slot has freed up for the next key in turn
+                this.admit();
+                Poll::Ready(Some(output))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<K: Ord + Clone, F: Future> FusedStream for FairQueue<K, F> {
+    fn is_terminated(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K, F> fmt::Debug for FairQueue<K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FairQueue {{ ... }}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::ready;
+    use futures::StreamExt;
+    use futures_test::task::noop_context;
+
+    fn drain<K: Ord + Clone, F: Future>(queue: &mut FairQueue<K, F>) -> Vec<(K, F::Output)> {
+        let mut cx = noop_context();
+        let mut outputs = vec![];
+        while let Poll::Ready(Some(x)) = queue.poll_next_unpin(&mut cx) {
+            outputs.push(x);
+        }
+        outputs
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut queue = FairQueue::new(1);
+        queue.push('a', ready(0));
+        for i in 1..5 {
+            queue.push('a', ready(i));
+        }
+        queue.push('b', ready(5));
+        queue.push('c', ready(6));
+        queue.push('b', ready(7));
+
+        assert_eq!(queue.pending_len(), 7);
+        assert_eq!(queue.pending_len_for(&'a'), 4);
+
+        let outputs = drain(&mut queue);
+        assert_eq!(
+            outputs,
+            [
+                ('a', 0),
+                ('a', 1),
+                ('b', 5),
+                ('c', 6),
+                ('a', 2),
+                ('b', 7),
+                ('a', 3),
+                ('a', 4)
+            ]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn weighted() {
+        let mut queue = FairQueue::new(1);
+        queue.set_weight('a', 3);
+        queue.push('x', ready(0));
+        for i in 1..6 {
+            queue.push('a', ready(i));
+        }
+        for i in 6..9 {
+            queue.push('b', ready(i));
+        }
+
+        let keys: Vec<_> = drain(&mut queue).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, ['x', 'a', 'a', 'a', 'b', 'a', 'a', 'b', 'b']);
+    }
+}
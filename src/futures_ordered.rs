@@ -1,6 +1,5 @@
-use crate::futures_ordered_bounded::OrderWrapper;
-use crate::FuturesUnordered;
-use alloc::collections::binary_heap::{BinaryHeap, PeekMut};
+use crate::futures_ordered_bounded::{OrderWrapper, ReorderBuffer};
+use crate::{FuturesUnordered, PollBudget};
 use core::fmt;
 use core::iter::FromIterator;
 use core::num::Wrapping;
@@ -48,7 +47,7 @@ use futures_core::{
 #[must_use = "streams do nothing unless polled"]
 pub struct FuturesOrdered<T: Future> {
     in_progress_queue: FuturesUnordered<OrderWrapper<T>>,
-    queued_outputs: BinaryHeap<OrderWrapper<T::Output>>,
+    queued_outputs: ReorderBuffer<T::Output>,
     next_incoming_index: Wrapping<usize>,
     next_outgoing_index: Wrapping<usize>,
 }
@@ -64,7 +63,7 @@ impl<Fut: Future> FuturesOrdered<Fut> {
         // todo: make const
         Self {
             in_progress_queue: FuturesUnordered::new(),
-            queued_outputs: BinaryHeap::new(),
+            queued_outputs: ReorderBuffer::with_capacity(0),
             next_incoming_index: Wrapping(0),
             next_outgoing_index: Wrapping(0),
         }
@@ -77,7 +76,7 @@ impl<Fut: Future> FuturesOrdered<Fut> {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             in_progress_queue: FuturesUnordered::with_capacity(capacity),
-            queued_outputs: BinaryHeap::with_capacity(capacity - 1),
+            queued_outputs: ReorderBuffer::with_capacity(capacity),
             next_incoming_index: Wrapping(0),
             next_outgoing_index: Wrapping(0),
         }
@@ -92,6 +91,18 @@ impl<Fut: Future> FuturesOrdered<Fut> {
         self.in_progress_queue.len() + self.queued_outputs.len()
     }
 
+    /// Returns the poll budget of the queue.
+    pub fn poll_budget(&self) -> PollBudget {
+        self.in_progress_queue.poll_budget()
+    }
+
+    /// Changes how many futures the queue polls in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn set_poll_budget(&mut self, budget: PollBudget) {
+        self.in_progress_queue.set_poll_budget(budget);
+    }
+
     /// Returns `true` if the queue contains no futures
     pub fn is_empty(&self) -> bool {
         self.in_progress_queue.is_empty() && self.queued_outputs.is_empty()
@@ -120,6 +131,7 @@ impl<Fut: Future> FuturesOrdered<Fut> {
     /// complete.
     pub fn push_front(&mut self, future: Fut) {
         self.next_outgoing_index -= 1;
+        self.queued_outputs.retreat();
         self.in_progress_queue.push(OrderWrapper {
             data: future,
             index: self.next_outgoing_index.0,
@@ -139,41 +151,22 @@ impl<Fut: Future> Stream for FuturesOrdered<Fut> {
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = &mut *self;
 
-        const MSB: usize = !(usize::MAX >> 1);
-        // house keeping if the indices gets too high
-        if this.next_outgoing_index.0 & MSB == MSB {
-            let mut ready_queue = core::mem::take(&mut this.queued_outputs).into_vec();
-            for entry in &mut ready_queue {
-                entry.index ^= MSB;
-            }
-            this.queued_outputs = ready_queue.into();
-
-            for group in this.in_progress_queue.groups.iter_mut() {
-                for task in group.tasks.iter_mut() {
-                    *task.project().index ^= MSB;
-                }
-            }
-
-            this.next_outgoing_index.0 ^= MSB;
-            this.next_incoming_index.0 ^= MSB;
-        }
-
         // Check to see if we've already received the next value
-        if let Some(next_output) = this.queued_outputs.peek_mut() {
-            if next_output.index == this.next_outgoing_index.0 {
-                this.next_outgoing_index += 1;
-                return Poll::Ready(Some(PeekMut::pop(next_output).data));
-            }
+        if let Some(output) = this.queued_outputs.pop_front() {
+            this.next_outgoing_index += 1;
+            return Poll::Ready(Some(output));
         }
 
         loop {
             match ready!(Pin::new(&mut this.in_progress_queue).poll_next(cx)) {
                 Some(output) => {
-                    if output.index == this.next_outgoing_index.0 {
+                    let offset = (Wrapping(output.index) - this.next_outgoing_index).0;
+                    if offset == 0 {
                         this.next_outgoing_index += 1;
+                        this.queued_outputs.advance();
                         return Poll::Ready(Some(output.data));
                     } else {
-                        this.queued_outputs.push(output)
+                        this.queued_outputs.insert(offset, output.data)
                     }
                 }
                 None => return Poll::Ready(None),
@@ -208,7 +201,7 @@ impl<Fut: Future> FromIterator<Fut> for FuturesOrdered<Fut> {
         }));
         Self {
             in_progress_queue,
-            queued_outputs: BinaryHeap::new(),
+            queued_outputs: ReorderBuffer::with_capacity(0),
             next_incoming_index: index,
             next_outgoing_index: Wrapping(0),
         }
@@ -1,6 +1,5 @@
-use crate::FuturesUnorderedBounded;
-use alloc::collections::binary_heap::{BinaryHeap, PeekMut};
-use core::cmp::Ordering;
+use crate::{FuturesUnorderedBounded, Key, PollBudget};
+use alloc::collections::VecDeque;
 use core::fmt;
 use core::iter::FromIterator;
 use core::num::Wrapping;
@@ -24,27 +23,6 @@ pin_project! {
     }
 }
 
-impl<T> PartialEq for OrderWrapper<T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.index == other.index
-    }
-}
-
-impl<T> Eq for OrderWrapper<T> {}
-
-impl<T> PartialOrd for OrderWrapper<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl<T> Ord for OrderWrapper<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // BinaryHeap is a max heap, so compare backwards here.
-        other.index.cmp(&self.index)
-    }
-}
-
 impl<T> Future for OrderWrapper<T>
 where
     T: Future,
@@ -60,6 +38,118 @@ where
     }
 }
 
+/// Outputs that completed before the next output to be returned.
+///
+/// Outputs are stored by their offset from the next outgoing index, so reordering is O(1)
+/// and the indices are free to wrap around.
+///
+/// When the reorder window is bounded, no output can be more than the window ahead of the
+/// next outgoing index, so the ring has a fixed number of slots and never resizes.
+/// Otherwise, completed outputs leave the set of in-flight futures, so they can run arbitrarily
+/// far ahead of a slow head future. In that case the ring grows as needed, and shrinks back to
+/// its retained capacity once it drains.
+pub(crate) struct ReorderBuffer<T> {
+    slots: VecDeque<Option<T>>,
+    len: usize,
+    // the capacity to keep once the ring drains, or `None` if the ring has a fixed size
+    retained: Option<usize>,
+}
+
+impl<T> ReorderBuffer<T> {
+    /// The smallest capacity kept by a growable ring, so that an unbounded queue
+    /// does not allocate again for every burst of outputs.
+    const MIN_CAPACITY: usize = 32;
+
+    /// Creates a ring that grows as needed.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: VecDeque::with_capacity(capacity),
+            len: 0,
+            retained: Some(usize::max(capacity, Self::MIN_CAPACITY)),
+        }
+    }
+
+    /// Sets the largest offset that outputs can be stored at, which fixes the size of the ring.
+    ///
+    /// With `None`, the ring grows as needed.
+    pub(crate) fn set_window(&mut self, window: Option<usize>) {
+        match window {
+            Some(window) => {
+                // outputs already beyond the window keep their slots
+                let size = usize::max(window, self.slots.len());
+                self.slots.resize_with(size, || None);
+                self.slots.shrink_to_fit();
+                self.retained = None;
+            }
+            None => self.retained = Some(usize::max(self.slots.len(), Self::MIN_CAPACITY)),
+        }
+    }
+
+    /// The number of outputs stored.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stores the output that is `offset` places after the next outgoing index.
+    pub(crate) fn insert(&mut self, offset: usize, output: T) {
+        if offset >= self.slots.len() {
+            debug_assert!(
+                self.retained.is_some(),
+                "output is outside of the reorder window"
+            );
+            self.slots.resize_with(offset + 1, || None);
+        }
+        debug_assert!(self.slots[offset].is_none());
+        self.slots[offset] = Some(output);
+        self.len += 1;
+    }
+
+    /// Takes the output at the next outgoing index, if it has completed,
+    /// moving on to the following index.
+    pub(crate) fn pop_front(&mut self) -> Option<T> {
+        let output = self.slots.front_mut()?.take()?;
+        self.len -= 1;
+        self.advance();
+        Some(output)
+    }
+
+    /// Moves on to the following index without an output.
+    pub(crate) fn advance(&mut self) {
+        let Some(output) = self.slots.pop_front() else {
+            return;
+        };
+        debug_assert!(output.is_none());
+        match self.retained {
+            // the slot is reused for the end of the window
+            None => self.slots.push_back(None),
+            Some(retained) => {
+                // release the memory used by a burst of outputs, once they have all been returned
+                if self.len == 0 && self.slots.capacity() > retained {
+                    // every slot is empty, and `insert` will add them back as needed
+                    self.slots.clear();
+                    self.slots.shrink_to(retained);
+                }
+            }
+        }
+    }
+
+    /// Moves back to the preceding index, which has no output yet.
+    pub(crate) fn retreat(&mut self) {
+        if self.retained.is_none() {
+            // the window was not full, so the end of it is empty
+            let last = self.slots.pop_back();
+            debug_assert!(last.is_some_and(|output| output.is_none()));
+        } else if self.slots.is_empty() {
+            return;
+        }
+        self.slots.push_front(None);
+    }
+}
+
 /// An unbounded queue of futures.
 ///
 /// This "combinator" is similar to `FuturesUnordered`, but it imposes an order
@@ -95,9 +185,13 @@ where
 #[must_use = "streams do nothing unless polled"]
 pub struct FuturesOrderedBounded<T: Future> {
     pub(crate) in_progress_queue: FuturesUnorderedBounded<OrderWrapper<T>>,
-    queued_outputs: BinaryHeap<OrderWrapper<T::Output>>,
+    queued_outputs: ReorderBuffer<T::Output>,
+    // the keys of every future pushed but not yet returned, by their offset from the next outgoing
+    // index. Only tracked once a head-of-line policy first skips the head, see `skip_head`
+    keys: Option<VecDeque<Option<Key>>>,
     pub(crate) next_incoming_index: Wrapping<usize>,
     next_outgoing_index: Wrapping<usize>,
+    reorder_window: usize,
 }
 
 impl<T: Future> Unpin for FuturesOrderedBounded<T> {}
@@ -110,12 +204,40 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
     pub fn new(capacity: usize) -> Self {
         Self {
             in_progress_queue: FuturesUnorderedBounded::new(capacity),
-            queued_outputs: BinaryHeap::with_capacity(capacity - 1),
+            queued_outputs: ReorderBuffer::with_capacity(capacity),
+            keys: None,
             next_incoming_index: Wrapping(0),
             next_outgoing_index: Wrapping(0),
+            reorder_window: usize::MAX,
         }
     }
 
+    /// Limits how far ahead of the next output the queue may run.
+    ///
+    /// The window counts every future pushed but not yet returned, both those in-flight
+    /// and those that have completed but are waiting for earlier futures to complete.
+    /// Pushes are refused while the window is full, even if there is spare capacity for
+    /// more in-flight futures. This bounds the number of outputs held out of order.
+    ///
+    /// By default, the window is unbounded.
+    pub fn set_reorder_window(&mut self, window: usize) {
+        self.reorder_window = window;
+        self.queued_outputs
+            .set_window(Some(window).filter(|&window| window != usize::MAX));
+    }
+
+    /// Returns the size of the reorder window. See [`FuturesOrderedBounded::set_reorder_window`].
+    pub fn reorder_window(&self) -> usize {
+        self.reorder_window
+    }
+
+    /// Returns `true` if another future can be pushed into the queue.
+    pub(crate) fn has_room(&self) -> bool {
+        let in_window = (self.next_incoming_index - self.next_outgoing_index).0;
+        in_window < self.reorder_window
+            && self.in_progress_queue.tasks.len() < self.in_progress_queue.tasks.capacity()
+    }
+
     /// Returns the number of futures contained in the queue.
     ///
     /// This represents the total number of in-flight futures, both
@@ -125,6 +247,18 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
         self.in_progress_queue.len() + self.queued_outputs.len()
     }
 
+    /// Returns the poll budget of the queue.
+    pub fn poll_budget(&self) -> PollBudget {
+        self.in_progress_queue.poll_budget()
+    }
+
+    /// Changes how many futures the queue polls in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn set_poll_budget(&mut self, budget: PollBudget) {
+        self.in_progress_queue.set_poll_budget(budget);
+    }
+
     /// Returns `true` if the queue contains no futures
     pub fn is_empty(&self) -> bool {
         self.in_progress_queue.is_empty() && self.queued_outputs.is_empty()
@@ -138,16 +272,23 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
     /// task notifications.
     ///
     /// # Errors
-    /// This method will error if the buffer is currently full, returning the future back
+    /// This method will error if the buffer or the reorder window is currently full, returning the future back
     pub fn try_push_back(&mut self, future: Fut) -> Result<(), Fut> {
-        self.in_progress_queue.try_push_with(future, |future| {
+        if !self.has_room() {
+            return Err(future);
+        }
+        let key = self.in_progress_queue.try_push_with(future, |future| {
             let wrapped = OrderWrapper {
                 data: future,
                 index: self.next_incoming_index.0,
             };
             self.next_incoming_index += 1;
             wrapped
-        })
+        })?;
+        if let Some(keys) = &mut self.keys {
+            keys.push_back(Some(key));
+        }
+        Ok(())
     }
 
     /// Pushes a future to the front of the queue.
@@ -159,15 +300,23 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
     /// complete.
     ///
     /// # Errors
-    /// This method will error if the buffer is currently full, returning the future back
+    /// This method will error if the buffer or the reorder window is currently full, returning the future back
     pub fn try_push_front(&mut self, future: Fut) -> Result<(), Fut> {
-        self.in_progress_queue.try_push_with(future, |future| {
+        if !self.has_room() {
+            return Err(future);
+        }
+        let key = self.in_progress_queue.try_push_with(future, |future| {
             self.next_outgoing_index -= 1;
+            self.queued_outputs.retreat();
             OrderWrapper {
                 data: future,
                 index: self.next_outgoing_index.0,
             }
-        })
+        })?;
+        if let Some(keys) = &mut self.keys {
+            keys.push_front(Some(key));
+        }
+        Ok(())
     }
 
     /// Pushes a future to the back of the queue.
@@ -178,7 +327,7 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
     /// task notifications.
     ///
     /// # Panics
-    /// This method will panic if the buffer is currently full. See [`FuturesOrderedBounded::try_push_back`] to get a result instead
+    /// This method will panic if the buffer or the reorder window is currently full. See [`FuturesOrderedBounded::try_push_back`] to get a result instead
     #[track_caller]
     pub fn push_back(&mut self, future: Fut) {
         if self.try_push_back(future).is_err() {
@@ -195,7 +344,7 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
     /// complete.
     ///
     /// # Panics
-    /// This method will panic if the buffer is currently full. See [`FuturesOrderedBounded::try_push_front`] to get a result instead
+    /// This method will panic if the buffer or the reorder window is currently full. See [`FuturesOrderedBounded::try_push_front`] to get a result instead
     #[track_caller]
     pub fn push_front(&mut self, future: Fut) {
         if self.try_push_front(future).is_err() {
@@ -204,45 +353,79 @@ impl<Fut: Future> FuturesOrderedBounded<Fut> {
     }
 }
 
-impl<Fut: Future> Stream for FuturesOrderedBounded<Fut> {
-    type Item = Fut::Output;
+impl<Fut: Future> FuturesOrderedBounded<Fut> {
+    /// The index of the next output to be returned.
+    pub(crate) fn head_index(&self) -> usize {
+        self.next_outgoing_index.0
+    }
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = &mut *self;
+    /// The number of completed outputs waiting for the head future to complete.
+    pub(crate) fn completed_successors(&self) -> usize {
+        self.queued_outputs.len()
+    }
 
-        const MSB: usize = !(usize::MAX >> 1);
-        // house keeping if the indices gets too high
-        if this.next_outgoing_index.0 & MSB == MSB {
-            let mut ready_queue = core::mem::take(&mut this.queued_outputs).into_vec();
-            for entry in &mut ready_queue {
-                entry.index ^= MSB;
+    /// Drops the head future, so that the next output can be returned.
+    ///
+    /// The first skip looks up the key of every future in the queue, after which the keys
+    /// are kept up to date as futures are pushed, so later skips don't have to search.
+    pub(crate) fn skip_head(&mut self) {
+        let keys = match &mut self.keys {
+            Some(keys) => keys,
+            None => {
+                let len = (self.next_incoming_index - self.next_outgoing_index).0;
+                let mut keys = VecDeque::new();
+                keys.resize(len, None);
+                let tasks = &mut self.in_progress_queue.tasks;
+                for i in 0..tasks.allocated() {
+                    if let Some(task) = tasks.get(i) {
+                        let offset = (Wrapping(task.index) - self.next_outgoing_index).0;
+                        keys[offset] = Some(tasks.key(i));
+                    }
+                }
+                self.keys.insert(keys)
             }
-            this.queued_outputs = ready_queue.into();
+        };
+        if let Some(Some(key)) = keys.front() {
+            self.in_progress_queue.cancel(*key);
+        }
+        self.queued_outputs.advance();
+        self.advance_head();
+    }
 
-            for task in this.in_progress_queue.tasks.iter_mut() {
-                *task.project().index ^= MSB;
+    /// Moves on to the next output, once the head has been returned or skipped.
+    fn advance_head(&mut self) {
+        self.next_outgoing_index += 1;
+        if let Some(keys) = &mut self.keys {
+            keys.pop_front();
+            if self.queued_outputs.is_empty() {
+                keys.shrink_to(self.in_progress_queue.capacity());
             }
-
-            this.next_outgoing_index.0 ^= MSB;
-            this.next_incoming_index.0 ^= MSB;
         }
+    }
+}
+
+impl<Fut: Future> Stream for FuturesOrderedBounded<Fut> {
+    type Item = Fut::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
 
         // Check to see if we've already received the next value
-        if let Some(next_output) = this.queued_outputs.peek_mut() {
-            if next_output.index == this.next_outgoing_index.0 {
-                this.next_outgoing_index += 1;
-                return Poll::Ready(Some(PeekMut::pop(next_output).data));
-            }
+        if let Some(output) = this.queued_outputs.pop_front() {
+            this.advance_head();
+            return Poll::Ready(Some(output));
         }
 
         loop {
             match ready!(Pin::new(&mut this.in_progress_queue).poll_next(cx)) {
                 Some(output) => {
-                    if output.index == this.next_outgoing_index.0 {
-                        this.next_outgoing_index += 1;
+                    let offset = (Wrapping(output.index) - this.next_outgoing_index).0;
+                    if offset == 0 {
+                        this.queued_outputs.advance();
+                        this.advance_head();
                         return Poll::Ready(Some(output.data));
                     } else {
-                        this.queued_outputs.push(output)
+                        this.queued_outputs.insert(offset, output.data)
                     }
                 }
                 None => return Poll::Ready(None),
@@ -276,10 +459,12 @@ impl<Fut: Future> FromIterator<Fut> for FuturesOrderedBounded<Fut> {
             }
         }));
         Self {
+            queued_outputs: ReorderBuffer::with_capacity(in_progress_queue.capacity()),
             in_progress_queue,
-            queued_outputs: BinaryHeap::new(),
+            keys: None,
             next_incoming_index: index,
             next_outgoing_index: Wrapping(0),
+            reorder_window: usize::MAX,
         }
     }
 }
@@ -363,4 +548,154 @@ mod tests {
         assert_eq!(buffer.len(), 10);
         assert_eq!(buffer.size_hint(), (10, Some(10)));
     }
+
+    #[test]
+    fn reorder_window() {
+        use futures::channel::oneshot;
+
+        let mut buffer = FuturesOrderedBounded::new(10);
+        buffer.set_reorder_window(3);
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+        let mut receivers = receivers.into_iter();
+        for rx in receivers.by_ref().take(3) {
+            buffer.push_back(rx);
+        }
+        let last = receivers.next().unwrap();
+        let last = buffer.try_push_back(last).unwrap_err();
+
+        // completed outputs still count towards the window until they are returned
+        let mut senders = senders.into_iter();
+        let first = senders.next().unwrap();
+        for (i, tx) in senders.by_ref().take(2).enumerate() {
+            tx.send(i + 1).unwrap();
+        }
+        let mut cx = noop_context();
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(buffer.in_progress_queue.len(), 1);
+        let last = buffer.try_push_back(last).unwrap_err();
+
+        first.send(0).unwrap();
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(0))));
+        buffer.push_back(last);
+    }
+
+    #[test]
+    fn out_of_order() {
+        use futures::channel::oneshot;
+
+        let mut buffer = FuturesOrderedBounded::new(5);
+        let mut cx = noop_context();
+
+        // start close to overflow, so the indices wrap around
+        buffer.next_incoming_index.0 = usize::MAX - 1;
+        buffer.next_outgoing_index.0 = usize::MAX - 1;
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..5).map(|_| oneshot::channel()).unzip();
+        let mut receivers = receivers.into_iter();
+        for rx in receivers.by_ref().take(4) {
+            buffer.push_back(rx);
+        }
+        for (i, tx) in senders.into_iter().enumerate().rev() {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(0))));
+        assert_eq!(buffer.len(), 3);
+
+        buffer.push_front(receivers.next().unwrap());
+        for i in [4, 1, 2, 3] {
+            assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(i))));
+        }
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(None));
+        assert!(buffer.queued_outputs.slots.is_empty());
+    }
+
+    #[test]
+    fn skip_head() {
+        use futures::channel::oneshot;
+
+        let mut buffer = FuturesOrderedBounded::new(4);
+        let mut cx = noop_context();
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+        let mut receivers = receivers.into_iter();
+        buffer.push_back(receivers.next().unwrap());
+        buffer.push_back(receivers.next().unwrap());
+        buffer.push_front(receivers.next().unwrap());
+
+        // the future pushed to the front is the head, even though it took a later slot
+        buffer.skip_head();
+        assert!(senders[2].is_canceled());
+        assert!(!senders[0].is_canceled());
+
+        // the slot is reused, which must not confuse the old key
+        buffer.push_back(receivers.next().unwrap());
+        senders.into_iter().enumerate().for_each(|(i, tx)| {
+            let _ = tx.send(i);
+        });
+        buffer.skip_head();
+        for i in [1, 3] {
+            assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(i))));
+        }
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(None));
+        assert_eq!(buffer.keys.map(|keys| keys.len()), Some(0));
+    }
+
+    #[test]
+    fn shrink_after_burst() {
+        use futures::channel::oneshot;
+
+        let mut buffer = FuturesOrderedBounded::new(64);
+        let mut cx = noop_context();
+
+        let (head, rx) = oneshot::channel();
+        buffer.push_back(rx);
+
+        // complete a burst of outputs while the head is stuck
+        for i in 1..1000 {
+            let (tx, rx) = oneshot::channel();
+            tx.send(i).unwrap();
+            buffer.push_back(rx);
+            assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Pending);
+        }
+        assert_eq!(buffer.queued_outputs.len(), 999);
+        assert!(buffer.queued_outputs.slots.capacity() >= 999);
+
+        head.send(0).unwrap();
+        for i in 0..1000 {
+            assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(i))));
+        }
+        assert!(buffer.queued_outputs.slots.capacity() <= 64);
+        assert!(buffer.keys.is_none());
+    }
+
+    #[test]
+    fn fixed_window() {
+        use futures::channel::oneshot;
+
+        let mut buffer = FuturesOrderedBounded::new(4);
+        buffer.set_reorder_window(3);
+        let mut cx = noop_context();
+        let capacity = buffer.queued_outputs.slots.capacity();
+
+        for _ in 0..100 {
+            let (senders, receivers): (Vec<_>, Vec<_>) = (0..3).map(|_| oneshot::channel()).unzip();
+            let mut receivers = receivers.into_iter();
+            let first = receivers.next().unwrap();
+            for rx in receivers {
+                buffer.push_back(rx);
+            }
+            buffer.push_front(first);
+
+            // complete the window out of order
+            for (i, tx) in senders.into_iter().enumerate().rev() {
+                tx.send(i).unwrap();
+            }
+            for i in 0..3 {
+                assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(i))));
+            }
+            assert_eq!(buffer.queued_outputs.slots.len(), 3);
+            assert_eq!(buffer.queued_outputs.slots.capacity(), capacity);
+        }
+    }
 }
@@ -0,0 +1,427 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::{
+    fmt,
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{
+    futures_ordered_bounded::OrderWrapper, FuturesUnorderedBounded, HeadAction, NoTimer, Timer,
+};
+
+/// Error yielded by [`FuturesSequenced`] in place of sequence numbers that never arrived,
+/// when using [`HeadAction::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    /// The sequence numbers that were missing.
+    pub missing: Range<usize>,
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sequence numbers {}..{} are missing",
+            self.missing.start, self.missing.end
+        )
+    }
+}
+
+impl core::error::Error for Gap {}
+
+/// Decides what [`FuturesSequenced`] does when the next sequence number has not been pushed,
+/// but later ones have.
+///
+/// The missing sequence number takes the place of the head in [`HeadAction`].
+/// With [`HeadAction::Wait`], the set waits for the missing sequence number forever,
+/// see [`GapPolicy::wait`]. Otherwise, once the gap has been waited on for the timeout,
+/// the set moves on to the next sequence number that was pushed, and
+/// either skips the gap or yields `Err(Gap)` in its place.
+#[derive(Debug, Clone)]
+pub struct GapPolicy<T = NoTimer> {
+    action: HeadAction,
+    timeout: Duration,
+    timer: T,
+}
+
+impl GapPolicy {
+    /// Creates a policy that waits for missing sequence numbers forever, so no timer is needed.
+    pub fn wait() -> Self {
+        Self::new(HeadAction::Wait, Duration::ZERO, NoTimer)
+    }
+}
+
+impl<T: Timer> GapPolicy<T> {
+    /// Creates a new policy, which waits `timeout` for the missing sequence numbers to be
+    /// pushed before applying `action` to the gap. `timer` is used to track the timeout.
+    ///
+    /// The timeout is ignored with [`HeadAction::Wait`].
+    pub fn new(action: HeadAction, timeout: Duration, timer: T) -> Self {
+        Self {
+            action,
+            timeout,
+            timer,
+        }
+    }
+}
+
+pin_project!(
+    /// A bounded set of futures, returning their outputs in the order of a user supplied
+    /// sequence number.
+    ///
+    /// This is similar to [`FuturesOrderedBounded`](crate::FuturesOrderedBounded), except the
+    /// order comes from the sequence number given to [`push`](FuturesSequenced::push) rather than
+    /// the order of the pushes. Every sequence number may only be pushed once, but they can be
+    /// pushed in any order. Outputs are returned as `Ok((sequence, output))`, starting
+    /// at the first sequence number given to [`FuturesSequenced::new`]. The stream ends
+    /// after returning sequence number `usize::MAX`.
+    ///
+    /// The sequence may have gaps, which are handled by the [`GapPolicy`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[tokio::main] async fn main() {
+    /// use futures::StreamExt;
+    /// use futures_buffered::{FuturesSequenced, Gap, GapPolicy, HeadAction};
+    /// use std::{future::ready, time::Duration};
+    ///
+    /// // `Tokio` implements `Timer`, as shown in the `Timer` example
+    #[doc = include_str!("doc/tokio_timer.md")]
+    ///
+    /// let policy = GapPolicy::new(HeadAction::Error, Duration::from_millis(10), Tokio);
+    /// let mut set = FuturesSequenced::new(3, 10, policy);
+    /// set.push(13, ready("d"));
+    /// set.push(11, ready("b"));
+    /// set.push(10, ready("a"));
+    ///
+    /// let mut set = std::pin::pin!(set);
+    /// assert_eq!(set.next().await, Some(Ok((10, "a"))));
+    /// assert_eq!(set.next().await, Some(Ok((11, "b"))));
+    /// assert_eq!(set.next().await, Some(Err(Gap { missing: 12..13 })));
+    /// assert_eq!(set.next().await, Some(Ok((13, "d"))));
+    /// assert_eq!(set.next().await, None);
+    /// # }
+    /// ```
+    #[must_use = "streams do nothing unless polled"]
+    pub struct FuturesSequenced<F: Future, T: Timer> {
+        in_progress_queue: FuturesUnorderedBounded<OrderWrapper<F>>,
+        in_progress: BTreeSet<usize>,
+        queued_outputs: BTreeMap<usize, F::Output>,
+        next_sequence: usize,
+        // set once `usize::MAX` has been returned
+        exhausted: bool,
+        policy: GapPolicy<T>,
+        #[pin]
+        sleep: Option<T::Sleep>,
+        // the sequence number that the gap timer was armed for
+        armed: Option<usize>,
+    }
+);
+
+impl<F: Future, T: Timer> FuturesSequenced<F, T> {
+    /// Constructs a new, empty `FuturesSequenced` with the given fixed capacity,
+    /// which returns outputs starting at sequence number `first`.
+    pub fn new(capacity: usize, first: usize, policy: GapPolicy<T>) -> Self {
+        Self {
+            in_progress_queue: FuturesUnorderedBounded::new(capacity),
+            in_progress: BTreeSet::new(),
+            queued_outputs: BTreeMap::new(),
+            next_sequence: first,
+            exhausted: false,
+            policy,
+            sleep: None,
+            armed: None,
+        }
+    }
+
+    /// Pushes a future with the given sequence number.
+    ///
+    /// This function will not call `poll` on the submitted future. The caller
+    /// must ensure that `FuturesSequenced::poll_next` is called in order to receive
+    /// task notifications.
+    ///
+    /// # Errors
+    /// This method will error if the buffer is currently full, or if the sequence number has
+    /// already been pushed or passed, returning the future back
+    pub fn try_push(&mut self, sequence: usize, future: F) -> Result<(), F> {
+        if self.exhausted
+            || sequence < self.next_sequence
+            || self.in_progress.contains(&sequence)
+            || self.queued_outputs.contains_key(&sequence)
+        {
+            return Err(future);
+        }
+        self.in_progress_queue
+            .try_push_with(future, |future| OrderWrapper {
+                data: future,
+                index: sequence,
+            })?;
+        self.in_progress.insert(sequence);
+        Ok(())
+    }
+
+    /// Pushes a future with the given sequence number.
+    ///
+    /// This function will not call `poll` on the submitted future. The caller
+    /// must ensure that `FuturesSequenced::poll_next` is called in order to receive
+    /// task notifications.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full, or if the sequence number has
+    /// already been pushed or passed. See [`FuturesSequenced::try_push`] to get a result instead
+    #[track_caller]
+    pub fn push(&mut self, sequence: usize, future: F) {
+        if self.try_push(sequence, future).is_err() {
+            panic!(
+                "attempted to push into a full `FuturesSequenced`, or with a used sequence number"
+            )
+        }
+    }
+
+    /// Returns the sequence number of the next output to be returned.
+    pub fn next_sequence(&self) -> usize {
+        self.next_sequence
+    }
+
+    /// Returns the number of futures contained in the set.
+    ///
+    /// This represents the total number of in-flight futures, both
+    /// those currently processing and those that have completed but
+    /// which are waiting for earlier sequence numbers.
+    pub fn len(&self) -> usize {
+        self.in_progress.len() + self.queued_outputs.len()
+    }
+
+    /// Returns `true` if the set contains no futures
+    pub fn is_empty(&self) -> bool {
+        self.in_progress.is_empty() && self.queued_outputs.is_empty()
+    }
+
+    /// Returns the number of futures that can be processing at once.
+    pub fn capacity(&self) -> usize {
+        self.in_progress_queue.capacity()
+    }
+}
+
+impl<F: Future, T: Timer> Stream for FuturesSequenced<F, T> {
+    type Item = Result<(usize, F::Output), Gap>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if *this.exhausted {
+            return Poll::Ready(None);
+        }
+
+        // collect everything that has completed, so we know what is left in the sequence
+        while let Poll::Ready(Some(output)) = this.in_progress_queue.poll_inner(cx) {
+            let output = output.1;
+            this.in_progress.remove(&output.index);
+            this.queued_outputs.insert(output.index, output.data);
+        }
+
+        loop {
+            let next = *this.next_sequence;
+            if let Some(output) = this.queued_outputs.remove(&next) {
+                match next.checked_add(1) {
+                    Some(following) => *this.next_sequence = following,
+                    None => *this.exhausted = true,
+                }
+                this.sleep.set(None);
+                *this.armed = None;
+                return Poll::Ready(Some(Ok((next, output))));
+            }
+
+            let present = match (this.in_progress.first(), this.queued_outputs.keys().next()) {
+                (Some(&a), Some(&b)) => a.min(b),
+                (Some(&a), None) | (None, Some(&a)) => a,
+                (None, None) => {
+                    this.sleep.set(None);
+                    *this.armed = None;
+                    return Poll::Ready(None);
+                }
+            };
+
+            // the next output is still in-flight, or we are waiting for the gap to fill
+            if present == next || this.policy.action == HeadAction::Wait {
+                this.sleep.set(None);
+                *this.armed = None;
+                return Poll::Pending;
+            }
+
+            if *this.armed != Some(next) {
+                let deadline = this.policy.timer.now() + this.policy.timeout;
+                this.sleep
+                    .set(Some(this.policy.timer.sleep_until(deadline)));
+                *this.armed = Some(next);
+            }
+            if let Some(sleep) = this.sleep.as_mut().as_pin_mut() {
+                if sleep.poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+            }
+
+            // give up on the gap
+            this.sleep.set(None);
+            *this.armed = None;
+            *this.next_sequence = present;
+            if this.policy.action == HeadAction::Error {
+                return Poll::Ready(Some(Err(Gap {
+                    missing: next..present,
+                })));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<F: Future, T: Timer> fmt::Debug for FuturesSequenced<F, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FuturesSequenced {{ ... }}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use futures::{channel::oneshot, Stream};
+    use futures_test::task::noop_context;
+
+    use super::*;
+    use crate::timer::mock::MockTimer;
+
+    #[test]
+    fn sequenced() {
+        let mut cx = noop_context();
+        let mut set = FuturesSequenced::new(4, 5, GapPolicy::wait());
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+        for (rx, seq) in receivers.into_iter().zip([8, 6, 5, 7]) {
+            set.push(seq, rx);
+        }
+        assert!(set.try_push(9, oneshot::channel().1).is_err());
+
+        for (tx, seq) in senders.into_iter().zip([8, 6, 5, 7]).rev() {
+            tx.send(seq * 10).unwrap();
+        }
+
+        let mut set = core::pin::pin!(set);
+        for seq in 5..9 {
+            assert_eq!(
+                set.as_mut().poll_next(&mut cx),
+                Poll::Ready(Some(Ok((seq, Ok(seq * 10)))))
+            );
+        }
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Ready(None));
+
+        // sequence numbers that have already passed are refused
+        assert!(set.try_push(8, oneshot::channel().1).is_err());
+    }
+
+    #[test]
+    fn gap_wait() {
+        let mut cx = noop_context();
+        let timer = MockTimer::default();
+        let policy = GapPolicy::new(HeadAction::Wait, Duration::ZERO, timer.clone());
+        let mut set = FuturesSequenced::new(4, 0, policy);
+        let mut set = Pin::new(&mut set);
+
+        set.push(1, core::future::ready(1));
+        timer.advance(1000);
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        set.push(0, core::future::ready(0));
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((0, 0))))
+        );
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((1, 1))))
+        );
+    }
+
+    #[test]
+    fn gap_skip() {
+        let mut cx = noop_context();
+        let timer = MockTimer::default();
+        let policy = GapPolicy::new(HeadAction::Skip, Duration::from_millis(10), timer.clone());
+        let mut set = FuturesSequenced::new(4, 0, policy);
+        let mut set = Pin::new(&mut set);
+
+        set.push(0, core::future::ready(0));
+        set.push(3, core::future::ready(3));
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((0, 0))))
+        );
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        // the gap is filled in part before the timeout
+        timer.advance(5);
+        set.push(1, core::future::ready(1));
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((1, 1))))
+        );
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        // the timer restarts for the rest of the gap
+        timer.advance(5);
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Pending);
+        timer.advance(5);
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((3, 3))))
+        );
+        assert_eq!(set.next_sequence(), 4);
+    }
+
+    #[test]
+    fn gap_error() {
+        let mut cx = noop_context();
+        let timer = MockTimer::default();
+        let policy = GapPolicy::new(HeadAction::Error, Duration::ZERO, timer);
+        let mut set = FuturesSequenced::new(4, 0, policy);
+        let mut set = Pin::new(&mut set);
+
+        let (_tx, rx) = oneshot::channel::<()>();
+        set.push(4, rx);
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Err(Gap { missing: 0..4 })))
+        );
+        // the in-flight future at the head is not a gap
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Pending);
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn last_sequence() {
+        let mut cx = noop_context();
+        let mut set = FuturesSequenced::new(2, usize::MAX - 1, GapPolicy::wait());
+        let mut set = Pin::new(&mut set);
+
+        set.push(usize::MAX, core::future::ready(1));
+        set.push(usize::MAX - 1, core::future::ready(0));
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((usize::MAX - 1, 0))))
+        );
+        assert_eq!(
+            set.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok((usize::MAX, 1))))
+        );
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Ready(None));
+        assert!(set.try_push(usize::MAX, core::future::ready(2)).is_err());
+    }
+}
@@ -6,7 +6,7 @@ use core::{
     task::{Context, Poll},
 };
 
-use crate::FuturesUnorderedBounded;
+use crate::{metrics::Recorder, FuturesUnorderedBounded, Key, PollBudget};
 use futures_core::{FusedStream, Stream};
 
 /// A set of futures which may complete in any order.
@@ -111,6 +111,9 @@ pub struct FuturesUnordered<F> {
     rem: usize,
     pub(crate) groups: Vec<FuturesUnorderedBounded<F>>,
     poll_next: usize,
+    // metrics of the groups that have been dropped
+    metrics: Recorder,
+    budget: PollBudget,
 }
 
 const MIN_CAPACITY: usize = 32;
@@ -134,6 +137,8 @@ impl<F> FuturesUnordered<F> {
             rem: 0,
             groups: Vec::new(),
             poll_next: 0,
+            metrics: Recorder::new(),
+            budget: PollBudget::DEFAULT,
         }
     }
 
@@ -148,6 +153,8 @@ impl<F> FuturesUnordered<F> {
                 rem: 0,
                 groups: Vec::from_iter([FuturesUnorderedBounded::new(n)]),
                 poll_next: 0,
+                metrics: Recorder::new(),
+                budget: PollBudget::DEFAULT,
             }
         } else {
             Self::new()
@@ -161,25 +168,88 @@ impl<F> FuturesUnordered<F> {
     /// ensure that [`FuturesUnordered::poll_next`](Stream::poll_next) is called
     /// in order to receive wake-up notifications for the given future.
     pub fn push(&mut self, fut: F) {
+        self.push_keyed(fut);
+    }
+
+    /// Push a future into the set, returning a [`Key`] that refers to it.
+    ///
+    /// The key can be used to [`cancel`](FuturesUnordered::cancel) the future,
+    /// or to access it via [`get_pin_mut`](FuturesUnordered::get_pin_mut), while
+    /// it is still in-flight. See [`FuturesUnordered::poll_next_keyed`] to receive
+    /// the key back alongside the output.
+    pub fn push_keyed(&mut self, fut: F) -> Key {
         self.rem += 1;
 
         let last = match self.groups.last_mut() {
             Some(last) => last,
             None => {
-                self.groups.push(FuturesUnorderedBounded::new(MIN_CAPACITY));
+                let mut first = FuturesUnorderedBounded::new(MIN_CAPACITY);
+                first.set_poll_budget(self.budget);
+                self.groups.push(first);
                 self.groups.last_mut().unwrap()
             }
         };
-        match last.try_push(fut) {
-            Ok(()) => {}
+        match last.try_push_keyed(fut) {
+            Ok(key) => outer_key(last, key),
             Err(future) => {
                 let mut next = FuturesUnorderedBounded::new(last.capacity() * 2);
-                next.push(future);
+                next.set_poll_budget(self.budget);
+                let key = next.push_keyed(future);
+                let key = outer_key(&next, key);
                 self.groups.push(next);
+                key
             }
         }
     }
 
+    /// Push a future into the set, entering `span` every time the future is polled.
+    ///
+    /// See [`FuturesUnordered::push`] for more details.
+    #[cfg(feature = "tracing")]
+    pub fn push_with_span(&mut self, fut: F, span: tracing::Span) {
+        let key = self.push_keyed(fut);
+        let group = self
+            .groups
+            .iter_mut()
+            .find(|group| owns_key(group, key))
+            .expect("the key should belong to a group");
+        let key = inner_key(group, key);
+        group
+            .tasks
+            .set_span(key.index, crate::instrument::SlotSpan::new(span));
+    }
+
+    /// Returns `true` if the future referred to by `key` is still in the set.
+    pub fn contains(&self, key: Key) -> bool {
+        self.groups
+            .iter()
+            .find(|group| owns_key(group, key))
+            .is_some_and(|group| group.contains(inner_key(group, key)))
+    }
+
+    /// Returns a pinned mutable reference to the future referred to by `key`,
+    /// if it is still in the set.
+    pub fn get_pin_mut(&mut self, key: Key) -> Option<Pin<&mut F>> {
+        let group = self.groups.iter_mut().find(|group| owns_key(group, key))?;
+        let key = inner_key(group, key);
+        group.get_pin_mut(key)
+    }
+
+    /// Cancels the future referred to by `key`, dropping it in place.
+    ///
+    /// Returns `false` if the future has already completed or was already cancelled.
+    pub fn cancel(&mut self, key: Key) -> bool {
+        let Some(group) = self.groups.iter_mut().find(|group| owns_key(group, key)) else {
+            return false;
+        };
+        let key = inner_key(group, key);
+        let cancelled = group.cancel(key);
+        if cancelled {
+            self.rem -= 1;
+        }
+        cancelled
+    }
+
     /// Returns `true` if the set contains no futures.
     pub fn is_empty(&self) -> bool {
         self.rem == 0
@@ -203,17 +273,74 @@ impl<F> FuturesUnordered<F> {
             }
         }
     }
+
+    /// Returns the poll budget of the set.
+    pub fn poll_budget(&self) -> PollBudget {
+        self.budget
+    }
+
+    /// Changes how many futures the set polls in one go, before yielding back to the executor.
+    ///
+    /// The budget applies to each of the [`FuturesUnorderedBounded`] groups making up the set.
+    /// See [`PollBudget`] for more details.
+    pub fn set_poll_budget(&mut self, budget: PollBudget) {
+        self.budget = budget;
+        for group in &mut self.groups {
+            group.set_poll_budget(budget);
+        }
+    }
+
+    /// Returns a snapshot of the metrics recorded by this set, across all of its groups.
+    ///
+    /// Requires the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> crate::Metrics {
+        let mut metrics = self.metrics.snapshot();
+        metrics.allocated_bytes =
+            core::mem::size_of::<FuturesUnorderedBounded<F>>() * self.groups.capacity();
+        for group in &self.groups {
+            metrics.merge(group.metrics());
+        }
+        metrics
+    }
 }
 
-impl<F: Future> Stream for FuturesUnordered<F> {
-    type Item = F::Output;
+// Every group has double the capacity of the group before it, so a group with capacity `cap`
+// can own the disjoint range of indices `cap..2*cap` in the keys handed out by the set.
+fn owns_key<F>(group: &FuturesUnorderedBounded<F>, key: Key) -> bool {
+    let cap = group.capacity();
+    cap <= key.index && key.index - cap < cap
+}
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+fn inner_key<F>(group: &FuturesUnorderedBounded<F>, key: Key) -> Key {
+    Key {
+        index: key.index - group.capacity(),
+        generation: key.generation,
+    }
+}
+
+fn outer_key<F>(group: &FuturesUnorderedBounded<F>, key: Key) -> Key {
+    Key {
+        index: key.index + group.capacity(),
+        generation: key.generation,
+    }
+}
+
+impl<F: Future> FuturesUnordered<F> {
+    /// Attempt to pull out the next value of this set, along with the [`Key`]
+    /// of the future that produced it.
+    ///
+    /// This behaves like [`FuturesUnordered::poll_next`](Stream::poll_next),
+    /// but is useful for correlating outputs with futures pushed via
+    /// [`FuturesUnordered::push_keyed`].
+    pub fn poll_next_keyed(&mut self, cx: &mut Context<'_>) -> Poll<Option<(Key, F::Output)>> {
         let Self {
             rem,
             groups,
             poll_next,
-        } = &mut *self;
+            metrics,
+            budget: _,
+        } = self;
         if groups.is_empty() {
             return Poll::Ready(None);
         }
@@ -223,11 +350,11 @@ impl<F: Future> Stream for FuturesUnordered<F> {
                 *poll_next = 0;
             }
 
-            let poll = Pin::new(&mut groups[*poll_next]).poll_next(cx);
-            match poll {
-                Poll::Ready(Some(x)) => {
+            let group = &mut groups[*poll_next];
+            match group.poll_next_keyed(cx) {
+                Poll::Ready(Some((key, x))) => {
                     *rem -= 1;
-                    return Poll::Ready(Some(x));
+                    return Poll::Ready(Some((outer_key(group, key), x)));
                 }
                 Poll::Ready(None) => {
                     let group = groups.remove(*poll_next);
@@ -245,6 +372,8 @@ impl<F: Future> Stream for FuturesUnordered<F> {
                     if *poll_next == groups.len() {
                         groups.push(group);
                         *poll_next = 0;
+                    } else {
+                        metrics.drop_group(&group);
                     }
                 }
                 Poll::Pending => {
@@ -254,6 +383,18 @@ impl<F: Future> Stream for FuturesUnordered<F> {
         }
         Poll::Pending
     }
+}
+
+impl<F: Future> Stream for FuturesUnordered<F> {
+    type Item = F::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.poll_next_keyed(cx) {
+            Poll::Ready(Some((_, x))) => Poll::Ready(Some(x)),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         (self.rem, Some(self.rem))
@@ -360,6 +501,7 @@ mod tests {
         }
     }
 
+    #[allow(dead_code)]
     struct Sleep {
         until: Instant,
     }
@@ -464,6 +606,36 @@ mod tests {
         assert_eq!(buffer.size_hint(), (10, Some(10)));
     }
 
+    #[test]
+    fn keyed() {
+        let mut buffer = FuturesUnordered::with_capacity(1);
+        let a = buffer.push_keyed(ready(1));
+        let b = buffer.push_keyed(ready(2));
+        let c = buffer.push_keyed(ready(3));
+        assert!(buffer.contains(a) && buffer.contains(b) && buffer.contains(c));
+        assert!(buffer.get_pin_mut(c).is_some());
+
+        assert!(buffer.cancel(b));
+        assert!(!buffer.cancel(b));
+        assert!(!buffer.contains(b));
+        assert_eq!(buffer.len(), 2);
+
+        let mut cx = futures_test::task::noop_context();
+        let mut outputs = vec![];
+        while let Poll::Ready(Some(x)) = buffer.poll_next_keyed(&mut cx) {
+            outputs.push(x);
+        }
+        outputs.sort_by_key(|(_, x)| *x);
+        assert_eq!(outputs, [(a, 1), (c, 3)]);
+        assert!(buffer.is_empty());
+
+        // the slot of `a` is reused, but the stale key must not refer to the new future
+        let d = buffer.push_keyed(ready(4));
+        assert!(buffer.contains(d));
+        assert!(!buffer.contains(a));
+        assert!(!buffer.cancel(a));
+    }
+
     #[test]
     fn multi() {
         fn wait(count: &Cell<usize>) -> PollCounter<'_, Yield> {
@@ -535,4 +707,27 @@ mod tests {
             }
         }
     }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        let mut buffer = FuturesUnordered::new();
+        for i in 0..40 {
+            buffer.push(ready(i));
+        }
+        let metrics = buffer.metrics();
+        assert_eq!(metrics.groups, 2);
+        assert_eq!(metrics.slot_polls.len(), 32 + 64);
+        assert_eq!(metrics.polls, 0);
+
+        let outputs: Vec<_> = futures::executor::block_on(buffer.by_ref().collect());
+        assert_eq!(outputs.len(), 40);
+
+        // the first group is dropped once it is empty, but its polls are kept
+        let metrics = buffer.metrics();
+        assert_eq!(metrics.groups, 1);
+        assert_eq!(metrics.slot_polls.len(), 64);
+        assert_eq!(metrics.polls, 40);
+        assert_eq!(metrics.spurious_polls, 0);
+    }
 }
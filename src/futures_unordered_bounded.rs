@@ -1,11 +1,23 @@
 use core::{
     fmt,
     future::Future,
+    marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use crate::{arc_slice::ArcSlice, slot_map::SlotMap};
+#[cfg(feature = "stall-detector")]
+use crate::stall::{StallDetector, StallReport};
+use crate::{
+    arc_slice::{ArcSlice, ReadySlot},
+    budget::PollBudget,
+    instrument::SlotSpan,
+    metrics::Recorder,
+    slot_map::{Key, SlotMap},
+};
+#[cfg(feature = "stall-detector")]
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use futures_core::{FusedStream, Stream};
 
 /// A set of futures which may complete in any order.
@@ -107,10 +119,64 @@ use futures_core::{FusedStream, Stream};
 pub struct FuturesUnorderedBounded<F> {
     pub(crate) tasks: SlotMap<F>,
     pub(crate) shared: ArcSlice,
+    // wake queues replaced by `set_capacity`, which in-flight futures might still be using
+    retired: Vec<ArcSlice>,
+    metrics: Recorder,
+    #[cfg(feature = "stall-detector")]
+    stalls: Option<Box<StallDetector>>,
+    budget: PollBudget,
 }
 
 impl<F> Unpin for FuturesUnorderedBounded<F> {}
 
+/// A builder for a [`FuturesUnorderedBounded`], created by [`FuturesUnorderedBounded::builder`].
+#[must_use = "builders do nothing unless built"]
+pub struct FuturesUnorderedBoundedBuilder<F> {
+    cap: usize,
+    budget: PollBudget,
+    _marker: PhantomData<fn() -> F>,
+}
+
+impl<F> FuturesUnorderedBoundedBuilder<F> {
+    /// Sets how many futures the set polls in one go, before yielding back to the executor.
+    /// The default is 61.
+    ///
+    /// See [`PollBudget`] for more details.
+    ///
+    /// # Panics
+    /// This method will panic if `limit` is 0
+    #[track_caller]
+    pub fn poll_budget(mut self, limit: usize) -> Self {
+        self.budget.limit = PollBudget::new(limit).limit;
+        self
+    }
+
+    /// Also spend tokio's cooperative scheduling budget while polling the set.
+    ///
+    /// See [`PollBudget::tokio_coop`] for more details.
+    #[cfg(feature = "tokio-coop")]
+    pub fn tokio_coop(mut self, enabled: bool) -> Self {
+        self.budget = self.budget.tokio_coop(enabled);
+        self
+    }
+
+    /// Creates the [`FuturesUnorderedBounded`].
+    pub fn build(self) -> FuturesUnorderedBounded<F> {
+        let mut set = FuturesUnorderedBounded::new(self.cap);
+        set.set_poll_budget(self.budget);
+        set
+    }
+}
+
+impl<F> fmt::Debug for FuturesUnorderedBoundedBuilder<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FuturesUnorderedBoundedBuilder")
+            .field("cap", &self.cap)
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
 impl<F> FuturesUnorderedBounded<F> {
     /// Constructs a new, empty [`FuturesUnorderedBounded`] with the given fixed capacity.
     ///
@@ -118,12 +184,54 @@ impl<F> FuturesUnorderedBounded<F> {
     /// In this state, [`FuturesUnorderedBounded::poll_next`](Stream::poll_next) will
     /// return [`Poll::Ready(None)`](Poll::Ready).
     pub fn new(cap: usize) -> Self {
+        let mut metrics = Recorder::new();
+        metrics.grow(cap);
         Self {
             tasks: SlotMap::new(cap),
             shared: ArcSlice::new(cap),
+            retired: Vec::new(),
+            metrics,
+            #[cfg(feature = "stall-detector")]
+            stalls: None,
+            budget: PollBudget::DEFAULT,
         }
     }
 
+    /// Creates a builder for a [`FuturesUnorderedBounded`] with the given fixed capacity,
+    /// to configure the set before it is created.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use futures_buffered::FuturesUnorderedBounded;
+    /// use std::future::Ready;
+    ///
+    /// let queue: FuturesUnorderedBounded<Ready<i32>> = FuturesUnorderedBounded::builder(16)
+    ///     .poll_budget(8)
+    ///     .build();
+    /// assert_eq!(queue.capacity(), 16);
+    /// assert_eq!(queue.poll_budget().limit(), 8);
+    /// ```
+    pub fn builder(cap: usize) -> FuturesUnorderedBoundedBuilder<F> {
+        FuturesUnorderedBoundedBuilder {
+            cap,
+            budget: PollBudget::DEFAULT,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the poll budget of the set.
+    pub fn poll_budget(&self) -> PollBudget {
+        self.budget
+    }
+
+    /// Changes how many futures the set polls in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn set_poll_budget(&mut self, budget: PollBudget) {
+        self.budget = budget;
+    }
+
     /// Push a future into the set.
     ///
     /// This method adds the given future to the set. This method will not
@@ -150,17 +258,97 @@ impl<F> FuturesUnorderedBounded<F> {
     /// # Errors
     /// This method will error if the buffer is currently full, returning the future back
     pub fn try_push(&mut self, fut: F) -> Result<(), F> {
+        self.try_push_with(fut, core::convert::identity).map(|_| ())
+    }
+
+    /// Push a future into the set, returning a [`Key`] that refers to it.
+    ///
+    /// The key can be used to [`cancel`](FuturesUnorderedBounded::cancel) the future,
+    /// or to access it via [`get_pin_mut`](FuturesUnorderedBounded::get_pin_mut), while
+    /// it is still in-flight. See [`FuturesUnorderedBounded::poll_next_keyed`] to receive
+    /// the key back alongside the output.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full. See [`FuturesUnorderedBounded::try_push_keyed`] to get a result instead
+    #[track_caller]
+    pub fn push_keyed(&mut self, fut: F) -> Key {
+        match self.try_push_keyed(fut) {
+            Ok(key) => key,
+            Err(_) => panic!("attempted to push into a full `FuturesUnorderedBounded`"),
+        }
+    }
+
+    /// Push a future into the set, returning a [`Key`] that refers to it.
+    ///
+    /// See [`FuturesUnorderedBounded::push_keyed`] for more details.
+    ///
+    /// # Errors
+    /// This method will error if the buffer is currently full, returning the future back
+    pub fn try_push_keyed(&mut self, fut: F) -> Result<Key, F> {
         self.try_push_with(fut, core::convert::identity)
     }
 
+    /// Push a future into the set, entering `span` every time the future is polled.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full. See [`FuturesUnorderedBounded::try_push_with_span`] to get a result instead
+    #[cfg(feature = "tracing")]
+    #[track_caller]
+    pub fn push_with_span(&mut self, fut: F, span: tracing::Span) {
+        self.push_spanned(fut, SlotSpan::new(span));
+    }
+
+    /// Push a future into the set, entering `span` every time the future is polled.
+    ///
+    /// # Errors
+    /// This method will error if the buffer is currently full, returning the future back
+    #[cfg(feature = "tracing")]
+    pub fn try_push_with_span(&mut self, fut: F, span: tracing::Span) -> Result<(), F> {
+        let key = self.try_push_keyed(fut)?;
+        self.tasks.set_span(key.index, SlotSpan::new(span));
+        Ok(())
+    }
+
+    #[track_caller]
+    pub(crate) fn push_spanned(&mut self, fut: F, span: SlotSpan) -> Key {
+        let key = self.push_keyed(fut);
+        self.tasks.set_span(key.index, span);
+        key
+    }
+
     #[inline]
-    pub(crate) fn try_push_with<T>(&mut self, t: T, f: impl FnMut(T) -> F) -> Result<(), T> {
-        let i = self.tasks.insert_with(t, f)?;
-        // safety: i is always within capacity
+    pub(crate) fn try_push_with<T>(&mut self, t: T, f: impl FnMut(T) -> F) -> Result<Key, T> {
+        let key = self.tasks.insert_with(t, f)?;
+        // safety: key.index is always within capacity
         unsafe {
-            self.shared.push(i);
+            self.shared.push(key.index);
         }
-        Ok(())
+        Ok(key)
+    }
+
+    /// Returns `true` if the future referred to by `key` is still in the set.
+    pub fn contains(&self, key: Key) -> bool {
+        self.tasks.contains_key(key)
+    }
+
+    /// Returns a pinned mutable reference to the future referred to by `key`,
+    /// if it is still in the set.
+    pub fn get_pin_mut(&mut self, key: Key) -> Option<Pin<&mut F>> {
+        self.tasks.get_by_key(key)
+    }
+
+    /// Cancels the future referred to by `key`, dropping it in place.
+    ///
+    /// Returns `false` if the future has already completed or was already cancelled.
+    pub fn cancel(&mut self, key: Key) -> bool {
+        self.tasks.remove_key(key)
+    }
+
+    /// Drops every future in the set.
+    ///
+    /// This does not change the capacity of the set.
+    pub fn clear(&mut self) {
+        self.tasks.clear();
     }
 
     /// Returns `true` if the set contains no futures.
@@ -179,6 +367,161 @@ impl<F> FuturesUnorderedBounded<F> {
     pub fn capacity(&self) -> usize {
         self.tasks.capacity()
     }
+
+    /// Changes the number of futures that can be contained in the set.
+    ///
+    /// Growing the set allocates new storage without moving or dropping any of
+    /// the in-flight futures. The storage at least doubles each time it has to grow,
+    /// so growing one future at a time stays cheap. Shrinking the set keeps all in-flight
+    /// futures, but new futures will not be accepted until [`len`](FuturesUnorderedBounded::len)
+    /// falls below the new capacity. Shrinking does not free any memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::future::ready;
+    /// use futures_buffered::FuturesUnorderedBounded;
+    ///
+    /// let mut queue = FuturesUnorderedBounded::new(1);
+    /// queue.push(ready(1));
+    /// assert!(queue.try_push(ready(2)).is_err());
+    ///
+    /// queue.set_capacity(2);
+    /// queue.push(ready(2));
+    /// assert_eq!(queue.len(), 2);
+    /// ```
+    pub fn set_capacity(&mut self, cap: usize) {
+        if cap > self.tasks.allocated() {
+            self.tasks.grow(cap);
+            let allocated = self.tasks.allocated();
+            self.metrics.grow(allocated);
+            #[cfg(feature = "stall-detector")]
+            if let Some(stalls) = &mut self.stalls {
+                stalls.grow(allocated);
+            }
+            let old = core::mem::replace(&mut self.shared, ArcSlice::new(allocated));
+
+            // The old wakers still push into the old queue, so we must keep
+            // draining it until the in-flight futures have let go of them.
+            // Any futures that were already queued must be polled again.
+            if self.tasks.is_empty() {
+                self.metrics.drop_queue(&old);
+            } else {
+                self.retired.push(old);
+            }
+        }
+        self.tasks.set_limit(cap);
+    }
+
+    /// Returns a snapshot of the metrics recorded by this set.
+    ///
+    /// Requires the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> crate::Metrics {
+        let mut metrics = self.metrics.snapshot();
+        metrics.groups = 1;
+        metrics.allocated_bytes = self.tasks.allocated_bytes();
+        for queue in core::iter::once(&self.shared).chain(&self.retired) {
+            metrics.wakes += queue.wakes();
+            metrics.allocated_bytes += queue.allocated_bytes();
+        }
+        metrics
+    }
+
+    /// Enables the stall detector, to help debug futures that never complete.
+    ///
+    /// Once enabled, the set reports any future that has not been woken for `idle_polls`
+    /// polls of the set. It also reports any future that returns [`Poll::Pending`] without
+    /// keeping a clone of its waker or waking itself, as nothing will be able to wake that
+    /// future again. The feature counts the live wakers of every slot, so it has a cost even
+    /// when the detector is not enabled.
+    ///
+    /// The reports can be collected with [`FuturesUnorderedBounded::take_stall_reports`].
+    ///
+    /// Requires the `stall-detector` feature.
+    ///
+    /// # Panics
+    /// This method will panic if `idle_polls` is 0
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use futures::StreamExt;
+    /// use futures_buffered::{FuturesUnorderedBounded, StallReport};
+    /// use futures_test::task::noop_context;
+    /// use std::future::poll_fn;
+    /// use std::task::Poll;
+    ///
+    /// let mut queue = FuturesUnorderedBounded::new(1);
+    /// queue.detect_stalls(10);
+    ///
+    /// // a future that forgets to register its waker
+    /// queue.push(poll_fn(|_cx| Poll::<()>::Pending));
+    /// assert!(queue.poll_next_unpin(&mut noop_context()).is_pending());
+    ///
+    /// assert_eq!(
+    ///     queue.take_stall_reports(),
+    ///     [StallReport::LostWakeup { slot: 0 }],
+    /// );
+    /// ```
+    #[cfg(feature = "stall-detector")]
+    #[track_caller]
+    pub fn detect_stalls(&mut self, idle_polls: usize) {
+        assert!(idle_polls > 0, "idle_polls must be at least 1");
+        self.stalls = Some(Box::new(StallDetector::new(
+            idle_polls,
+            self.tasks.allocated(),
+        )));
+    }
+
+    /// Returns the problems found by the stall detector since the last call.
+    ///
+    /// This is always empty unless [`FuturesUnorderedBounded::detect_stalls`] was called.
+    ///
+    /// Requires the `stall-detector` feature.
+    #[cfg(feature = "stall-detector")]
+    pub fn take_stall_reports(&mut self) -> Vec<StallReport> {
+        match &mut self.stalls {
+            Some(stalls) => stalls.take_reports(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Reports the slot if nothing is left that could wake it.
+    #[cfg(feature = "stall-detector")]
+    fn check_lost_wakeup(&mut self, index: usize) {
+        let Some(stalls) = &mut self.stalls else {
+            return;
+        };
+        let held = core::iter::once(&self.shared)
+            .chain(&self.retired)
+            .any(|queue| queue.is_queued(index) || queue.slot_wakers(index) > 0);
+        if !held {
+            stalls.lost_wakeup(index);
+        }
+    }
+
+    /// Pops the next woken slot, checking the retired wake queues first.
+    ///
+    /// # Safety
+    /// Requires mutual exclusion, see [`ArcSlice::pop`]
+    unsafe fn pop(&mut self) -> ReadySlot<usize> {
+        let mut i = 0;
+        while let Some(retired) = self.retired.get(i) {
+            // check uniqueness first - if there are no wakers left,
+            // nothing can be pushed after we see the queue is empty.
+            let unique = retired.is_unique();
+            match retired.pop() {
+                ReadySlot::None if unique => {
+                    let retired = self.retired.swap_remove(i);
+                    self.metrics.drop_queue(&retired);
+                }
+                ReadySlot::None => i += 1,
+                ready => return ready,
+            }
+        }
+        self.shared.pop()
+    }
 }
 
 type PollFn<F, O> = fn(Pin<&mut F>, cx: &mut Context<'_>) -> Poll<O>;
@@ -194,30 +537,64 @@ impl<F> FuturesUnorderedBounded<F> {
         }
 
         self.shared.register(cx.waker());
+        for retired in &self.retired {
+            retired.register(cx.waker());
+        }
+
+        #[cfg(feature = "stall-detector")]
+        if let Some(stalls) = &mut self.stalls {
+            let (tasks, shared, retired) = (&self.tasks, &self.shared, &self.retired);
+            stalls.poll(|i| {
+                tasks.is_occupied(i)
+                    && !core::iter::once(shared)
+                        .chain(retired)
+                        .any(|queue| queue.is_queued(i))
+            });
+        }
 
-        const MAX: usize = 61;
+        let budget = self.budget;
         let mut count = 0;
         loop {
             count += 1;
             // if we are in a pending only loop - let's break out.
-            if count > MAX {
+            if count > budget.limit {
+                self.metrics.budget_yield();
                 cx.waker().wake_by_ref();
                 return Poll::Pending;
             }
+            // the runtime will wake us once we have more budget
+            let Poll::Ready(progress) = budget.proceed(cx) else {
+                self.metrics.budget_yield();
+                return Poll::Pending;
+            };
 
-            match unsafe { self.shared.pop() } {
-                crate::arc_slice::ReadySlot::None => break,
-                crate::arc_slice::ReadySlot::Inconsistent => {
+            match unsafe { self.pop() } {
+                ReadySlot::None => break,
+                ReadySlot::Inconsistent => {
+                    self.metrics.inconsistent_retry();
                     cx.waker().wake_by_ref();
                     return Poll::Pending;
                 }
-                crate::arc_slice::ReadySlot::Ready((i, waker)) => {
-                    if let Some(task) = self.tasks.get(i) {
-                        let mut cx = Context::from_waker(&waker);
-
-                        let res = poll_fn(task, &mut cx);
+                ReadySlot::Ready(i) => {
+                    #[cfg(feature = "stall-detector")]
+                    if let Some(stalls) = &mut self.stalls {
+                        stalls.woken(i);
+                    }
+                    if let Some((task, span)) = self.tasks.get_with_span(i) {
+                        let res = {
+                            let waker = self.shared.waker(i);
+                            let mut cx = Context::from_waker(&waker);
+                            let _entered = span.enter();
+                            poll_fn(task, &mut cx)
+                        };
+                        self.metrics.poll(i, res.is_ready());
+                        #[cfg(feature = "stall-detector")]
+                        if res.is_pending() {
+                            self.check_lost_wakeup(i);
+                        }
 
                         if let Poll::Ready(x) = res {
+                            progress.made_progress();
                             return Poll::Ready(Some((i, x)));
                         }
                     }
@@ -241,6 +618,26 @@ impl<F: Future> FuturesUnorderedBounded<F> {
     }
 }
 
+impl<F: Future> FuturesUnorderedBounded<F> {
+    /// Attempt to pull out the next value of this set, along with the [`Key`]
+    /// of the future that produced it.
+    ///
+    /// This behaves like [`FuturesUnorderedBounded::poll_next`](Stream::poll_next),
+    /// but is useful for correlating outputs with futures pushed via
+    /// [`FuturesUnorderedBounded::push_keyed`].
+    pub fn poll_next_keyed(&mut self, cx: &mut Context<'_>) -> Poll<Option<(Key, F::Output)>> {
+        match self.poll_inner_no_remove(cx, F::poll) {
+            Poll::Ready(Some((i, x))) => {
+                let key = self.tasks.key(i);
+                self.tasks.remove(i);
+                Poll::Ready(Some((key, x)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
 impl<F: Future> Stream for FuturesUnorderedBounded<F> {
     type Item = F::Output;
 
@@ -324,8 +721,19 @@ impl<F> FromIterator<F> for FuturesUnorderedBounded<F> {
             }
         }
 
+        let mut metrics = Recorder::new();
+        metrics.grow(cap);
+
         // create the queue
-        Self { tasks, shared }
+        Self {
+            tasks,
+            shared,
+            retired: Vec::new(),
+            metrics,
+            #[cfg(feature = "stall-detector")]
+            stalls: None,
+            budget: PollBudget::DEFAULT,
+        }
     }
 }
 
@@ -352,9 +760,16 @@ mod tests {
         time::Duration,
     };
     use futures::{channel::oneshot, StreamExt};
-    use futures_test::task::noop_context;
+    use futures_test::task::{new_count_waker, noop_context};
     use pin_project_lite::pin_project;
-    use std::time::Instant;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        task::Waker,
+        time::Instant,
+    };
 
     pin_project!(
         struct PollCounter<'c, F> {
@@ -453,6 +868,125 @@ mod tests {
         assert_eq!(buffer.size_hint(), (10, Some(10)));
     }
 
+    #[test]
+    fn keyed() {
+        let mut buffer = FuturesUnorderedBounded::new(2);
+        let a = buffer.push_keyed(ready(1));
+        let b = buffer.push_keyed(ready(2));
+        assert_ne!(a, b);
+        assert!(buffer.contains(a));
+        assert!(buffer.get_pin_mut(b).is_some());
+
+        assert!(buffer.cancel(a));
+        assert!(!buffer.cancel(a));
+        assert!(!buffer.contains(a));
+        assert!(buffer.get_pin_mut(a).is_none());
+        assert_eq!(buffer.len(), 1);
+
+        assert_eq!(
+            buffer.poll_next_keyed(&mut noop_context()),
+            Poll::Ready(Some((b, 2)))
+        );
+        assert!(!buffer.contains(b));
+        assert_eq!(
+            buffer.poll_next_keyed(&mut noop_context()),
+            Poll::Ready(None)
+        );
+    }
+
+    #[test]
+    fn keyed_stale() {
+        let mut buffer = FuturesUnorderedBounded::new(1);
+        let a = buffer.push_keyed(ready(1));
+        assert!(buffer.cancel(a));
+
+        // the slot is reused, but the old key must not refer to the new future
+        let b = buffer.push_keyed(ready(2));
+        assert!(!buffer.contains(a));
+        assert!(!buffer.cancel(a));
+        assert!(buffer.contains(b));
+
+        assert_eq!(
+            buffer.poll_next_keyed(&mut noop_context()),
+            Poll::Ready(Some((b, 2)))
+        );
+    }
+
+    #[test]
+    fn grow_while_waiting() {
+        let mut buffer = FuturesUnorderedBounded::new(1);
+        let (tx, rx) = oneshot::channel();
+        let key = buffer.push_keyed(rx);
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+
+        // the oneshot holds onto a waker into the old storage
+        buffer.set_capacity(4);
+        assert_eq!(buffer.capacity(), 4);
+        assert!(buffer.contains(key));
+        for i in 0..3 {
+            buffer.push_keyed(oneshot::channel().1);
+            assert_eq!(buffer.len(), i + 2);
+        }
+        assert!(buffer.try_push(oneshot::channel().1).is_err());
+
+        tx.send(1).unwrap();
+        assert_eq!(
+            buffer.poll_next_keyed(&mut noop_context()),
+            Poll::Ready(Some((key, Ok(1))))
+        );
+    }
+
+    #[test]
+    fn grow_one_at_a_time() {
+        let mut buffer = FuturesUnorderedBounded::new(1);
+        let mut txs = vec![];
+        for cap in 1..=2000 {
+            buffer.set_capacity(cap);
+            let (tx, rx) = oneshot::channel();
+            buffer.push(rx);
+            txs.push(tx);
+            assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        }
+
+        // storage doubles each time, rather than growing by one slot
+        assert_eq!(buffer.tasks.allocated(), 2048);
+        assert_eq!(buffer.retired.len(), 11);
+
+        for (i, tx) in txs.into_iter().enumerate() {
+            tx.send(i).unwrap();
+        }
+        let outputs: Vec<_> = futures::executor::block_on(buffer.by_ref().collect());
+        assert_eq!(outputs.len(), 2000);
+        assert!(buffer.retired.is_empty());
+    }
+
+    #[test]
+    fn shrink() {
+        let mut buffer = FuturesUnorderedBounded::new(3);
+        for i in 0..3 {
+            buffer.push(ready(i));
+        }
+
+        buffer.set_capacity(1);
+        assert_eq!(buffer.capacity(), 1);
+        assert_eq!(buffer.len(), 3);
+        assert!(buffer.try_push(ready(3)).is_err());
+
+        futures::executor::block_on(buffer.next());
+        futures::executor::block_on(buffer.next());
+        assert!(buffer.try_push(ready(3)).is_err());
+        futures::executor::block_on(buffer.next());
+        assert!(buffer.try_push(ready(3)).is_ok());
+
+        // growing within the existing allocation
+        buffer.set_capacity(3);
+        buffer.push(ready(4));
+        buffer.push(ready(5));
+        let mut outputs: Vec<_> = futures::executor::block_on(buffer.collect());
+        outputs.sort();
+        assert_eq!(outputs, [3, 4, 5]);
+    }
+
     #[test]
     fn drop_while_waiting() {
         let mut buffer = FuturesUnorderedBounded::new(10);
@@ -566,4 +1100,303 @@ mod tests {
             assert_eq!(poll, Poll::Ready(Some(Ok(i))));
         }
     }
+
+    #[test]
+    fn repeated_wakes() {
+        let (outer, wakes) = new_count_waker();
+        let mut cx = Context::from_waker(&outer);
+
+        let polls = Cell::new(0);
+        let waker = Cell::new(None);
+        let mut buffer = FuturesUnorderedBounded::new(2);
+        buffer.push(poll_fn(|cx| {
+            polls.set(polls.get() + 1);
+            waker.set(Some(cx.waker().clone()));
+            Poll::<()>::Pending
+        }));
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(polls.get(), 1);
+
+        // the slot is only queued once, no matter how many times it is woken
+        let slot = waker.take().unwrap();
+        for _ in 0..1000 {
+            slot.wake_by_ref();
+        }
+        assert_eq!(wakes.get(), 1);
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(polls.get(), 2);
+
+        // and can be queued again once it has been polled
+        waker.take().unwrap().wake();
+        drop(slot);
+        assert_eq!(wakes.get(), 2);
+        assert_eq!(buffer.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(polls.get(), 3);
+    }
+
+    #[test]
+    fn cross_thread_wakes() {
+        struct Shared {
+            count: AtomicUsize,
+            waker: Mutex<Option<Waker>>,
+        }
+
+        const WAKES: usize = 10_000;
+        let shared: Vec<_> = (0..4)
+            .map(|_| {
+                Arc::new(Shared {
+                    count: AtomicUsize::new(0),
+                    waker: Mutex::new(None),
+                })
+            })
+            .collect();
+
+        let mut buffer = FuturesUnorderedBounded::new(4);
+        for (i, shared) in shared.iter().enumerate() {
+            let shared = shared.clone();
+            buffer.push(poll_fn(move |cx| {
+                *shared.waker.lock().unwrap() = Some(cx.waker().clone());
+                if shared.count.load(Ordering::SeqCst) == 2 * WAKES {
+                    Poll::Ready(i)
+                } else {
+                    Poll::Pending
+                }
+            }));
+        }
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+
+        std::thread::scope(|s| {
+            // two threads race to wake each slot
+            for shared in shared.iter().chain(&shared) {
+                s.spawn(move || {
+                    for _ in 0..WAKES {
+                        shared.count.fetch_add(1, Ordering::SeqCst);
+                        let waker = shared.waker.lock().unwrap().clone();
+                        waker.unwrap().wake();
+                    }
+                });
+            }
+
+            let mut outputs: Vec<_> = futures::executor::block_on(buffer.by_ref().collect());
+            outputs.sort();
+            assert_eq!(outputs, [0, 1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn poll_budget() {
+        let polls = Cell::new(0);
+        let mut buffer = FuturesUnorderedBounded::builder(2).poll_budget(5).build();
+        buffer.push(PollCounter {
+            count: &polls,
+            inner: poll_fn(|cx| {
+                cx.waker().wake_by_ref();
+                Poll::<()>::Pending
+            }),
+        });
+
+        // the set yields after polling the future 5 times
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        assert_eq!(polls.get(), 5);
+
+        buffer.set_poll_budget(PollBudget::new(2));
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        assert_eq!(polls.get(), 7);
+    }
+
+    #[cfg(feature = "tokio-coop")]
+    #[tokio::test]
+    async fn tokio_coop() {
+        let mut buffer = FuturesUnorderedBounded::builder(256)
+            .poll_budget(1024)
+            .tokio_coop(true)
+            .build();
+        for i in 0..256 {
+            buffer.push(ready(i));
+        }
+
+        // tokio only lets a task complete 128 futures before it has to yield
+        let outputs = poll_fn(|cx| {
+            let mut outputs = 0;
+            while let Poll::Ready(Some(_)) = buffer.poll_next_unpin(cx) {
+                outputs += 1;
+            }
+            Poll::Ready(outputs)
+        })
+        .await;
+        assert_eq!(outputs, 128);
+
+        tokio::task::yield_now().await;
+        assert_eq!(buffer.next().await, Some(128));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        let c = Cell::new(0);
+        let mut buffer = FuturesUnorderedBounded::new(2);
+        buffer.push(yield_now(&c));
+        futures::executor::block_on(buffer.next());
+
+        let metrics = buffer.metrics();
+        assert_eq!(metrics.slot_polls, [2, 0]);
+        assert_eq!(metrics.polls, 2);
+        assert_eq!(metrics.spurious_polls, 1);
+        assert_eq!(metrics.wakes, 1);
+        assert_eq!(metrics.budget_yields, 0);
+        assert_eq!(metrics.groups, 1);
+        assert!(metrics.allocated_bytes > 0);
+
+        // a future that always wakes itself uses up the poll budget
+        let mut buffer = FuturesUnorderedBounded::new(2);
+        buffer.push(poll_fn(|cx| {
+            cx.waker().wake_by_ref();
+            Poll::<()>::Pending
+        }));
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        let metrics = buffer.metrics();
+        assert_eq!(metrics.budget_yields, 1);
+        assert_eq!(metrics.polls, 61);
+        assert_eq!(metrics.spurious_polls, 61);
+
+        // wakes into a replaced wake queue are still counted
+        buffer.set_capacity(4);
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        let metrics = buffer.metrics();
+        assert_eq!(metrics.slot_polls.len(), 4);
+        assert_eq!(metrics.polls, 122);
+        assert_eq!(metrics.wakes, 122);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn span() {
+        use std::cell::RefCell;
+
+        let names = RefCell::new(vec![]);
+        let current_span = || {
+            poll_fn(|_| {
+                let span = tracing::Span::current();
+                names.borrow_mut().push(span.metadata().map(|m| m.name()));
+                Poll::Ready(())
+            })
+        };
+
+        tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+            let mut buffer = FuturesUnorderedBounded::new(3);
+            buffer.push_with_span(current_span(), tracing::info_span!("first"));
+            buffer.push(current_span());
+            assert!(buffer
+                .try_push_with_span(current_span(), tracing::info_span!("third"))
+                .is_ok());
+            futures::executor::block_on(buffer.by_ref().count());
+
+            // the slot is reused without the old span
+            buffer.push(current_span());
+            futures::executor::block_on(buffer.count());
+        });
+
+        assert_eq!(
+            names.into_inner(),
+            [Some("first"), None, Some("third"), None]
+        );
+    }
+
+    #[cfg(feature = "stall-detector")]
+    #[test]
+    fn lost_wakeup() {
+        let waker = Cell::new(None);
+        let mut buffer = FuturesUnorderedBounded::new(3);
+        buffer.detect_stalls(100);
+
+        let kept = |cx: &mut Context<'_>| {
+            waker.set(Some(cx.waker().clone()));
+            Poll::Pending
+        };
+        let woken = |cx: &mut Context<'_>| {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        };
+        let lost = |_: &mut Context<'_>| Poll::Pending;
+        buffer.push(Box::new(poll_fn(kept)) as Box<dyn Future<Output = ()> + Unpin + '_>);
+        buffer.push(Box::new(poll_fn(woken)));
+        buffer.push(Box::new(poll_fn(lost)));
+
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        let reports = buffer.take_stall_reports();
+        assert!(reports.contains(&StallReport::LostWakeup { slot: 2 }));
+        assert_eq!(reports.len(), 1);
+
+        // futures that keep their waker are not reported, even after being woken
+        waker.take().unwrap().wake();
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        assert_eq!(buffer.take_stall_reports(), []);
+    }
+
+    #[cfg(feature = "stall-detector")]
+    #[test]
+    fn idle() {
+        let mut buffer = FuturesUnorderedBounded::new(2);
+        buffer.detect_stalls(3);
+        let (tx, rx) = oneshot::channel();
+        buffer.push(rx);
+
+        for _ in 0..3 {
+            assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        }
+        assert_eq!(buffer.take_stall_reports(), []);
+
+        // reported once, until it is woken again
+        for _ in 0..3 {
+            assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        }
+        assert_eq!(
+            buffer.take_stall_reports(),
+            [StallReport::Idle { slot: 0, polls: 3 }]
+        );
+
+        tx.send(1).unwrap();
+        assert_eq!(
+            buffer.poll_next_unpin(&mut noop_context()),
+            Poll::Ready(Some(Ok(1)))
+        );
+        assert_eq!(buffer.take_stall_reports(), []);
+    }
+
+    #[cfg(feature = "stall-detector")]
+    #[test]
+    fn idle_after_wake() {
+        let mut buffer = FuturesUnorderedBounded::new(4);
+        buffer.detect_stalls(2);
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+        for rx in receivers {
+            buffer.push(rx);
+        }
+        let mut senders = senders.into_iter().map(Some).collect::<Vec<_>>();
+
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        senders[1].take().unwrap().send(1).unwrap();
+        assert_eq!(
+            buffer.poll_next_unpin(&mut noop_context()),
+            Poll::Ready(Some(Ok(1)))
+        );
+
+        // woken futures are not reported, even if they were waiting long enough
+        senders[3].take().unwrap().send(3).unwrap();
+        assert_eq!(
+            buffer.poll_next_unpin(&mut noop_context()),
+            Poll::Ready(Some(Ok(3)))
+        );
+        assert_eq!(
+            buffer.take_stall_reports(),
+            [
+                StallReport::Idle { slot: 0, polls: 2 },
+                StallReport::Idle { slot: 2, polls: 2 }
+            ]
+        );
+
+        // nor are the slots of completed futures
+        assert_eq!(buffer.poll_next_unpin(&mut noop_context()), Poll::Pending);
+        assert_eq!(buffer.take_stall_reports(), []);
+    }
 }
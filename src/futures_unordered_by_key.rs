@@ -0,0 +1,187 @@
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::Stream;
+
+use crate::{
+    keyed::{KeyedQueues, Tagged},
+    FuturesUnordered,
+};
+
+/// A set of futures which may complete in any order, running at most one future per key.
+///
+/// Every future is pushed with a key. Futures with different keys run concurrently, like in
+/// [`FuturesUnordered`], but a future pushed for a key that already has a running future is
+/// queued. It is started automatically once all the earlier futures for that key have
+/// completed, so futures with the same key run one at a time, in the order they were pushed.
+///
+/// Outputs are returned alongside their key, in the order in which they complete.
+///
+/// # Example
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::StreamExt;
+/// use futures_buffered::FuturesUnorderedByKey;
+/// use std::future::ready;
+///
+/// let mut set = FuturesUnorderedByKey::new();
+/// set.push("row 1", ready(1));
+/// set.push("row 2", ready(2));
+/// set.push("row 1", ready(3));
+///
+/// assert_eq!(set.running_len(), 2);
+/// assert_eq!(set.queued_len(), 1);
+///
+/// let mut outputs: Vec<_> = set.collect().await;
+/// outputs.sort();
+/// assert_eq!(outputs, [("row 1", 1), ("row 1", 3), ("row 2", 2)]);
+/// # });
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct FuturesUnorderedByKey<K, F> {
+    in_progress_queue: FuturesUnordered<Tagged<K, F>>,
+    // futures waiting for an earlier future with the same key to complete
+    queued: KeyedQueues<K, F>,
+}
+
+impl<K, F> Unpin for FuturesUnorderedByKey<K, F> {}
+
+impl<K: Ord, F> Default for FuturesUnorderedByKey<K, F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, F> FuturesUnorderedByKey<K, F> {
+    /// Constructs a new, empty `FuturesUnorderedByKey`.
+    ///
+    /// The returned set does not contain any futures.
+    /// In this state, [`FuturesUnorderedByKey::poll_next`](Stream::poll_next) will
+    /// return [`Poll::Ready(None)`](Poll::Ready).
+    pub fn new() -> Self {
+        Self {
+            in_progress_queue: FuturesUnordered::new(),
+            queued: KeyedQueues::new(),
+        }
+    }
+
+    /// Constructs a new, empty `FuturesUnorderedByKey` with space to run `n` futures
+    /// before needing to allocate.
+    pub fn with_capacity(n: usize) -> Self {
+        Self {
+            in_progress_queue: FuturesUnordered::with_capacity(n),
+            queued: KeyedQueues::new(),
+        }
+    }
+
+    /// Push a future into the set, with the given key.
+    ///
+    /// If a future with the same key is already running, this future is queued
+    /// until every earlier future with that key has completed.
+    ///
+    /// This function will not call `poll` on the submitted future. The caller
+    /// must ensure that [`FuturesUnorderedByKey::poll_next`](Stream::poll_next) is called
+    /// in order to receive wake-up notifications for the given future.
+    pub fn push(&mut self, key: K, fut: F)
+    where
+        K: Clone,
+    {
+        if let Some(fut) = self.queued.acquire(key.clone(), fut) {
+            self.in_progress_queue.push(Tagged::new(key, fut));
+        }
+    }
+
+    /// Returns `true` if the set contains no futures
+    pub fn is_empty(&self) -> bool {
+        self.in_progress_queue.is_empty()
+    }
+
+    /// Returns the number of futures contained in the set, both running and queued.
+    pub fn len(&self) -> usize {
+        self.running_len() + self.queued_len()
+    }
+
+    /// Returns the number of futures that are running.
+    pub fn running_len(&self) -> usize {
+        self.in_progress_queue.len()
+    }
+
+    /// Returns the number of futures that are queued behind a running future with the same key.
+    pub fn queued_len(&self) -> usize {
+        self.queued.waiting()
+    }
+}
+
+impl<K: Ord + Clone, F: Future> Stream for FuturesUnorderedByKey<K, F> {
+    type Item = (K, F::Output);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.in_progress_queue).poll_next(cx) {
+            Poll::Ready(Some((key, output))) => {
+                // the key is now free, so start the next future that was waiting for it
+                if let Some(fut) = this.queued.release(&key) {
+                    this.in_progress_queue.push(Tagged::new(key.clone(), fut));
+                }
+                Poll::Ready(Some((key, output)))
+            }
+            p => p,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<K, F> fmt::Debug for FuturesUnorderedByKey<K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FuturesUnorderedByKey {{ ... }}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::oneshot, StreamExt};
+    use futures_test::task::noop_context;
+
+    #[test]
+    fn exclusive() {
+        let mut cx = noop_context();
+        let mut set = FuturesUnorderedByKey::new();
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+        for (rx, key) in receivers.into_iter().zip([1, 2, 1, 1]) {
+            set.push(key, rx);
+        }
+        assert_eq!(set.running_len(), 2);
+        assert_eq!(set.queued_len(), 2);
+        assert_eq!(set.len(), 4);
+
+        // completing a queued future does nothing until it gets to run
+        let mut senders = senders.into_iter().map(Some).collect::<Vec<_>>();
+        senders[3].take().unwrap().send(3).unwrap();
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Pending);
+
+        senders[0].take().unwrap().send(0).unwrap();
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Ready(Some((1, Ok(0)))));
+        assert_eq!(set.running_len(), 2);
+        assert_eq!(set.queued_len(), 1);
+
+        senders[2].take().unwrap().send(2).unwrap();
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Ready(Some((1, Ok(2)))));
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Ready(Some((1, Ok(3)))));
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Pending);
+
+        senders[1].take().unwrap().send(1).unwrap();
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Ready(Some((2, Ok(1)))));
+        assert_eq!(set.poll_next_unpin(&mut cx), Poll::Ready(None));
+        assert!(set.is_empty());
+    }
+}
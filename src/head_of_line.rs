@@ -0,0 +1,345 @@
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{FuturesOrderedBounded, Timer};
+
+/// What to do with a head future that is holding up the outputs queued behind it.
+///
+/// This is also used by [`GapPolicy`](crate::GapPolicy), where the head is a missing
+/// sequence number rather than a future.
+///
+/// See [`HeadPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadAction {
+    /// Keep waiting for the head future, however long it takes.
+    Wait,
+    /// Drop the head future and continue with the next output.
+    Skip,
+    /// Drop the head future and yield `Err(Stalled)` in its place.
+    Error,
+}
+
+/// Placeholder error yielded in place of a head future that was given up on
+/// with [`HeadAction::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stalled;
+
+impl fmt::Display for Stalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("head of line future stalled")
+    }
+}
+
+impl core::error::Error for Stalled {}
+
+/// Decides when an ordered queue stops waiting for its head future.
+///
+/// The head is given up on once it has been the head for longer than the
+/// [`timeout`](HeadPolicy::timeout), or once enough of the futures behind it have completed
+/// (see [`successors`](HeadPolicy::successors)), whichever comes first.
+/// If neither is set, the head is waited for forever.
+///
+/// See [`FuturesOrderedBounded::with_head_policy`],
+/// [`buffered_ordered_with_head_policy`](crate::BufferedStreamExt::buffered_ordered_with_head_policy)
+/// and [`BufferedOrdered::with_head_policy`](crate::BufferedOrdered::with_head_policy).
+#[derive(Debug, Clone)]
+pub struct HeadPolicy<T> {
+    action: HeadAction,
+    timeout: Option<Duration>,
+    successors: Option<usize>,
+    timer: T,
+}
+
+impl<T: Timer> HeadPolicy<T> {
+    /// Creates a new policy, which applies `action` to a late head future.
+    /// `timer` is used to track the [`timeout`](HeadPolicy::timeout).
+    pub fn new(action: HeadAction, timer: T) -> Self {
+        Self {
+            action,
+            timeout: None,
+            successors: None,
+            timer,
+        }
+    }
+
+    /// Gives up on the head future once it has been at the head of the queue for `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Gives up on the head future once `n` of the futures behind it have completed.
+    pub fn successors(mut self, n: usize) -> Self {
+        self.successors = Some(n);
+        self
+    }
+}
+
+pin_project!(
+    /// Tracks how long the current head of an ordered queue has been waited on.
+    pub(crate) struct HeadState<T: Timer> {
+        policy: HeadPolicy<T>,
+        #[pin]
+        sleep: Option<T::Sleep>,
+        // the index of the head future that the timer was armed for
+        armed: Option<usize>,
+    }
+);
+
+impl<T: Timer> HeadState<T> {
+    pub(crate) fn new(policy: HeadPolicy<T>) -> Self {
+        Self {
+            policy,
+            sleep: None,
+            armed: None,
+        }
+    }
+
+    pub(crate) fn poll_next<Fut: Future>(
+        self: Pin<&mut Self>,
+        queue: &mut FuturesOrderedBounded<Fut>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Fut::Output, Stalled>>> {
+        let mut this = self.project();
+        loop {
+            if let Poll::Ready(x) = Pin::new(&mut *queue).poll_next(cx) {
+                // the head has changed, so restart the timer for the new head
+                Self::restart(this.policy, this.sleep, this.armed, queue);
+                return Poll::Ready(x.map(Ok));
+            }
+
+            if this.policy.action == HeadAction::Wait || queue.is_empty() {
+                return Poll::Pending;
+            }
+
+            let mut late = this
+                .policy
+                .successors
+                .is_some_and(|n| queue.completed_successors() >= n);
+
+            if !late && this.policy.timeout.is_some() {
+                if *this.armed != Some(queue.head_index()) {
+                    Self::restart(this.policy, this.sleep.as_mut(), this.armed, queue);
+                }
+                if let Some(sleep) = this.sleep.as_mut().as_pin_mut() {
+                    late = sleep.poll(cx).is_ready();
+                }
+            }
+
+            if !late {
+                return Poll::Pending;
+            }
+
+            queue.skip_head();
+            Self::restart(this.policy, this.sleep.as_mut(), this.armed, queue);
+            if this.policy.action == HeadAction::Error {
+                return Poll::Ready(Some(Err(Stalled)));
+            }
+        }
+    }
+
+    fn restart<Fut: Future>(
+        policy: &HeadPolicy<T>,
+        mut sleep: Pin<&mut Option<T::Sleep>>,
+        armed: &mut Option<usize>,
+        queue: &FuturesOrderedBounded<Fut>,
+    ) {
+        match policy.timeout {
+            Some(timeout) if policy.action != HeadAction::Wait && !queue.is_empty() => {
+                let deadline = policy.timer.now() + timeout;
+                sleep.set(Some(policy.timer.sleep_until(deadline)));
+                *armed = Some(queue.head_index());
+            }
+            _ => {
+                sleep.set(None);
+                *armed = None;
+            }
+        }
+    }
+}
+
+pin_project!(
+    /// An ordered queue of futures that gives up on a late head future.
+    ///
+    /// Created by [`FuturesOrderedBounded::with_head_policy`].
+    #[must_use = "streams do nothing unless polled"]
+    pub struct HeadOfLineOrdered<Fut: Future, T: Timer> {
+        pub(crate) queue: FuturesOrderedBounded<Fut>,
+        #[pin]
+        state: HeadState<T>,
+    }
+);
+
+impl<Fut: Future, T: Timer> HeadOfLineOrdered<Fut, T> {
+    /// Returns the underlying queue.
+    pub fn get_ref(&self) -> &FuturesOrderedBounded<Fut> {
+        &self.queue
+    }
+
+    /// Returns the underlying queue, which can be used to push more futures.
+    pub fn get_mut(&mut self) -> &mut FuturesOrderedBounded<Fut> {
+        &mut self.queue
+    }
+}
+
+impl<Fut: Future> FuturesOrderedBounded<Fut> {
+    /// Applies a [`HeadPolicy`] to this queue.
+    ///
+    /// The returned stream yields `Ok` for every output, in order. If the head future is
+    /// given up on, it is dropped and, depending on the [`HeadAction`], either skipped or
+    /// replaced by `Err(Stalled)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[tokio::main] async fn main() {
+    /// use futures::StreamExt;
+    /// use futures_buffered::{FuturesOrderedBounded, HeadAction, HeadPolicy, Stalled};
+    /// use std::time::Duration;
+    ///
+    /// // `Tokio` implements `Timer`, as shown in the `Timer` example
+    #[doc = include_str!("doc/tokio_timer.md")]
+    ///
+    /// let mut queue = FuturesOrderedBounded::new(2);
+    /// queue.push_back(tokio::time::sleep(Duration::from_secs(60)));
+    /// queue.push_back(tokio::time::sleep(Duration::from_millis(1)));
+    ///
+    /// let policy = HeadPolicy::new(HeadAction::Error, Tokio).timeout(Duration::from_millis(10));
+    /// let mut queue = std::pin::pin!(queue.with_head_policy(policy));
+    ///
+    /// assert_eq!(queue.next().await, Some(Err(Stalled)));
+    /// assert_eq!(queue.next().await, Some(Ok(())));
+    /// assert_eq!(queue.next().await, None);
+    /// # }
+    /// ```
+    pub fn with_head_policy<T: Timer>(self, policy: HeadPolicy<T>) -> HeadOfLineOrdered<Fut, T> {
+        HeadOfLineOrdered {
+            queue: self,
+            state: HeadState::new(policy),
+        }
+    }
+}
+
+impl<Fut: Future, T: Timer> Stream for HeadOfLineOrdered<Fut, T> {
+    type Item = Result<Fut::Output, Stalled>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        this.state.poll_next(this.queue, cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.queue.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use futures::{channel::oneshot, Stream};
+    use futures_test::task::noop_context;
+
+    use super::*;
+    use crate::timer::mock::MockTimer;
+
+    #[test]
+    fn successors() {
+        let mut cx = noop_context();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..4).map(|_| oneshot::channel()).unzip();
+
+        let policy = HeadPolicy::new(HeadAction::Skip, MockTimer::default()).successors(2);
+        let queue = FuturesOrderedBounded::from_iter(receivers).with_head_policy(policy);
+        let mut queue = core::pin::pin!(queue);
+
+        let mut senders = senders.into_iter();
+        let _stuck = senders.next().unwrap();
+
+        for (i, tx) in senders.enumerate() {
+            tx.send(i + 1).unwrap();
+        }
+
+        // the head is skipped once two outputs are queued behind it
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok(Ok(1))))
+        );
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok(Ok(2))))
+        );
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok(Ok(3))))
+        );
+        assert_eq!(queue.as_mut().poll_next(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut cx = noop_context();
+        let timer = MockTimer::default();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..3).map(|_| oneshot::channel()).unzip();
+
+        let policy =
+            HeadPolicy::new(HeadAction::Error, timer.clone()).timeout(Duration::from_millis(10));
+        let queue = FuturesOrderedBounded::from_iter(receivers).with_head_policy(policy);
+        let mut queue = core::pin::pin!(queue);
+
+        let mut senders = senders.into_iter();
+        let _stuck = senders.next().unwrap();
+        let second = senders.next().unwrap();
+        let _third = senders.next().unwrap();
+
+        second.send(1).unwrap();
+        assert_eq!(queue.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        timer.advance(10);
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Err(Stalled)))
+        );
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok(Ok(1))))
+        );
+
+        // the timer restarts for the new head
+        timer.advance(5);
+        assert_eq!(queue.as_mut().poll_next(&mut cx), Poll::Pending);
+        timer.advance(5);
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Err(Stalled)))
+        );
+        assert_eq!(queue.as_mut().poll_next(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn wait() {
+        let mut cx = noop_context();
+        let timer = MockTimer::default();
+        let (send, recv) = oneshot::channel();
+
+        let policy = HeadPolicy::new(HeadAction::Wait, timer.clone())
+            .timeout(Duration::from_millis(10))
+            .successors(0);
+        let queue = FuturesOrderedBounded::from_iter([recv]).with_head_policy(policy);
+        let mut queue = core::pin::pin!(queue);
+
+        timer.advance(100);
+        assert_eq!(queue.as_mut().poll_next(&mut cx), Poll::Pending);
+
+        send.send(1).unwrap();
+        assert_eq!(
+            queue.as_mut().poll_next(&mut cx),
+            Poll::Ready(Some(Ok(Ok(1))))
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use pin_project_lite::pin_project;
+
+use crate::FuturesUnorderedBounded;
+
+pin_project!(
+    #[must_use = "futures do nothing unless you `.await` or poll them"]
+    /// Future for the [`hedge`] function.
+    pub struct Hedge<F, R, S, D> {
+        queue: FuturesUnorderedBounded<F>,
+        request: R,
+        sleep_fn: S,
+        #[pin]
+        sleep: Option<D>,
+        after: Duration,
+        started: usize,
+    }
+);
+
+/// Creates a future which runs a hedged request.
+///
+/// The first copy of the request is started when the returned future is first polled.
+/// If no copy has finished `after` that, another copy is started, up to `copies` in total.
+/// The output of whichever copy finishes first is returned, and all the other copies are dropped.
+///
+/// Timers are created with `sleep`, which is given the delay to wait for. This keeps the
+/// combinator independent of any particular runtime.
+///
+/// # Panics
+///
+/// This function will panic if `copies` is 0.
+///
+/// # Examples
+///
+/// ```
+/// # #[tokio::main] async fn main() {
+/// use futures_buffered::hedge;
+/// use std::time::Duration;
+///
+/// async fn fetch() -> u32 {
+///     tokio::time::sleep(Duration::from_millis(10)).await;
+///     42
+/// }
+///
+/// let output = hedge(Duration::from_millis(5), 3, tokio::time::sleep, fetch).await;
+/// assert_eq!(output, 42);
+/// # }
+/// ```
+pub fn hedge<F, R, S, D>(after: Duration, copies: usize, sleep: S, request: R) -> Hedge<F, R, S, D>
+where
+    F: Future,
+    R: FnMut() -> F,
+    S: FnMut(Duration) -> D,
+    D: Future,
+{
+    assert!(copies > 0, "a hedged request needs at least one copy");
+    Hedge {
+        queue: FuturesUnorderedBounded::new(copies),
+        request,
+        sleep_fn: sleep,
+        sleep: None,
+        after,
+        started: 0,
+    }
+}
+
+impl<F, R, S, D> Hedge<F, R, S, D> {
+    /// Returns the number of copies of the request that have been started so far.
+    pub fn started(&self) -> usize {
+        self.started
+    }
+}
+
+impl<F, R, S, D> Future for Hedge<F, R, S, D>
+where
+    F: Future,
+    R: FnMut() -> F,
+    S: FnMut(Duration) -> D,
+    D: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        if *this.started == 0 {
+            this.queue.push((this.request)());
+            *this.started = 1;
+            if *this.started < this.queue.capacity() {
+                this.sleep.set(Some((this.sleep_fn)(*this.after)));
+            }
+        }
+
+        loop {
+            match this.queue.poll_inner(cx) {
+                Poll::Ready(Some((_, x))) => {
+                    // the first copy to finish wins, the rest are cancelled
+                    this.queue.clear();
+                    this.sleep.set(None);
+                    return Poll::Ready(x);
+                }
+                Poll::Ready(None) => panic!("Hedge polled after completion"),
+                Poll::Pending => {}
+            }
+
+            // start another copy once the delay has elapsed
+            match this.sleep.as_mut().as_pin_mut() {
+                Some(sleep) => match sleep.poll(cx) {
+                    Poll::Ready(_) => {
+                        this.queue.push((this.request)());
+                        *this.started += 1;
+                        if *this.started < this.queue.capacity() {
+                            this.sleep.set(Some((this.sleep_fn)(*this.after)));
+                        } else {
+                            this.sleep.set(None);
+                        }
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{cell::RefCell, future::ready, time::Duration};
+    use futures::{
+        channel::oneshot,
+        future::{pending, Ready},
+    };
+    use futures_test::task::noop_context;
+
+    use super::*;
+
+    #[test]
+    fn hedge_waits() {
+        let mut cx = noop_context();
+        let senders = RefCell::new(vec![]);
+
+        let mut fut = hedge(
+            Duration::from_secs(1),
+            3,
+            |_| pending::<()>(),
+            || {
+                let (send, recv) = oneshot::channel::<u32>();
+                senders.borrow_mut().push(send);
+                recv
+            },
+        );
+        let mut fut = Pin::new(&mut fut);
+
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(fut.started(), 1);
+
+        senders.borrow_mut().remove(0).send(1).unwrap();
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Ready(Ok(1)));
+    }
+
+    #[test]
+    fn hedge_copies() {
+        let mut cx = noop_context();
+        let senders = RefCell::new(vec![]);
+
+        let sleep = |after| {
+            assert_eq!(after, Duration::from_secs(1));
+            ready(())
+        };
+        let mut fut = hedge(Duration::from_secs(1), 3, sleep, || {
+            let (send, recv) = oneshot::channel::<u32>();
+            senders.borrow_mut().push(send);
+            recv
+        });
+        let mut fut = Pin::new(&mut fut);
+
+        // the sleep is always ready, so every copy starts straight away
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(fut.started(), 3);
+        assert_eq!(senders.borrow().len(), 3);
+
+        let last = senders.borrow_mut().pop().unwrap();
+        last.send(3).unwrap();
+        assert_eq!(fut.as_mut().poll(&mut cx), Poll::Ready(Ok(3)));
+
+        // the other copies have been dropped
+        assert!(senders.borrow().iter().all(|s| s.is_canceled()));
+    }
+
+    #[test]
+    fn hedge_single() {
+        let fut = hedge(
+            Duration::ZERO,
+            1,
+            |_| -> Ready<()> { unreachable!() },
+            || ready(7),
+        );
+        assert_eq!(futures::executor::block_on(fut), 7);
+    }
+}
@@ -0,0 +1,90 @@
+//! Support for entering a [`tracing::Span`](https://docs.rs/tracing/latest/tracing/struct.Span.html)
+//! whenever a future in a set is polled.
+//!
+//! Spans are only recorded with the `tracing` feature. Otherwise these types are
+//! zero sized, and entering a span does nothing.
+
+use core::marker::PhantomData;
+
+/// The span to enter when polling the future in a slot.
+pub(crate) struct SlotSpan {
+    #[cfg(feature = "tracing")]
+    span: Option<tracing::Span>,
+}
+
+/// Guard returned by [`SlotSpan::enter`], which exits the span when dropped.
+pub(crate) struct Entered<'a> {
+    #[cfg(feature = "tracing")]
+    _entered: Option<tracing::span::Entered<'a>>,
+    _marker: PhantomData<&'a SlotSpan>,
+}
+
+impl SlotSpan {
+    pub(crate) const NONE: Self = Self {
+        #[cfg(feature = "tracing")]
+        span: None,
+    };
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn new(span: tracing::Span) -> Self {
+        Self { span: Some(span) }
+    }
+
+    #[inline(always)]
+    pub(crate) fn enter(&self) -> Entered<'_> {
+        Entered {
+            #[cfg(feature = "tracing")]
+            _entered: self.span.as_ref().map(tracing::Span::enter),
+            _marker: PhantomData,
+        }
+    }
+}
+
+mod private_make_span {
+    pub trait Sealed<T> {}
+}
+
+/// Creates the span that each future pushed by an adaptor is polled in.
+///
+/// This is implemented by [`NoSpan`] and, with the `tracing` feature, by any
+/// `FnMut(&T) -> tracing::Span`. The closure is stored inline in the adaptor, so it
+/// does not need to be `Send`, `Sync` or `'static` unless the adaptor does.
+pub trait MakeSpan<T>: private_make_span::Sealed<T> {
+    #[doc(hidden)]
+    #[cfg(feature = "tracing")]
+    fn make_span(&mut self, item: &T) -> Option<tracing::Span>;
+}
+
+/// A [`MakeSpan`] that polls futures outside of any span.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSpan;
+
+impl<T> private_make_span::Sealed<T> for NoSpan {}
+
+impl<T> MakeSpan<T> for NoSpan {
+    #[cfg(feature = "tracing")]
+    #[inline(always)]
+    fn make_span(&mut self, _item: &T) -> Option<tracing::Span> {
+        None
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T, F: FnMut(&T) -> tracing::Span> private_make_span::Sealed<T> for F {}
+
+#[cfg(feature = "tracing")]
+impl<T, F: FnMut(&T) -> tracing::Span> MakeSpan<T> for F {
+    fn make_span(&mut self, item: &T) -> Option<tracing::Span> {
+        Some(self(item))
+    }
+}
+
+/// Creates the [`SlotSpan`] for `item` with `span_fn`.
+#[inline(always)]
+pub(crate) fn slot_span<T>(_span_fn: &mut impl MakeSpan<T>, _item: &T) -> SlotSpan {
+    #[cfg(feature = "tracing")]
+    if let Some(span) = _span_fn.make_span(_item) {
+        return SlotSpan::new(span);
+    }
+    SlotSpan::NONE
+}
@@ -7,6 +7,7 @@ use core::{
 };
 
 use crate::FuturesUnorderedBounded;
+use futures_core::{FusedStream, Stream};
 
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 /// Future for the [`join_all`] function.
@@ -85,6 +86,33 @@ where
     }
 }
 
+/// Creates a future which represents a collection of the outputs of the futures
+/// given, where each future is polled inside of a
+/// [`tracing::Span`](https://docs.rs/tracing/latest/tracing/struct.Span.html).
+///
+/// This behaves like [`join_all`], except `span_fn` is called with each future
+/// to create the span that it is polled in.
+///
+/// Requires the `tracing` feature.
+#[cfg(feature = "tracing")]
+pub fn join_all_with_span<I, SF>(iter: I, mut span_fn: SF) -> JoinAll<<I as IntoIterator>::Item>
+where
+    I: IntoIterator,
+    <I as IntoIterator>::Item: Future,
+    SF: FnMut(&<I as IntoIterator>::Item) -> tracing::Span,
+{
+    let mut join_all = join_all(iter);
+    let tasks = &mut join_all.queue.tasks;
+    for i in 0..tasks.len() {
+        let span = match tasks.get(i) {
+            Some(task) => span_fn(&task),
+            None => continue,
+        };
+        tasks.set_span(i, crate::instrument::SlotSpan::new(span));
+    }
+    join_all
+}
+
 impl<F: Future> Future for JoinAll<F> {
     type Output = Vec<F::Output>;
 
@@ -116,6 +144,71 @@ impl<F: Future> Future for JoinAll<F> {
     }
 }
 
+#[must_use = "streams do nothing unless polled"]
+/// Stream for the [`join_all_indexed`] function.
+pub struct JoinAllIndexed<F> {
+    queue: FuturesUnorderedBounded<F>,
+}
+
+impl<F> Unpin for JoinAllIndexed<F> {}
+
+/// Creates a stream which returns the outputs of the futures given as soon as they complete,
+/// along with the index of the future that produced it.
+///
+/// This is like [`join_all`], except outputs can be processed before all the futures have
+/// completed. The index refers to the position of the future in the given iterator.
+///
+/// # Examples
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::channel::oneshot;
+/// use futures::stream::StreamExt;
+/// use futures_buffered::join_all_indexed;
+///
+/// let (send_one, recv_one) = oneshot::channel();
+/// let (send_two, recv_two) = oneshot::channel();
+///
+/// let mut stream = join_all_indexed([recv_one, recv_two]);
+///
+/// send_two.send(2i32)?;
+/// assert_eq!(stream.next().await, Some((1, Ok(2i32))));
+///
+/// send_one.send(1i32)?;
+/// assert_eq!(stream.next().await, Some((0, Ok(1i32))));
+///
+/// assert_eq!(stream.next().await, None);
+/// # Ok::<(), i32>(()) }).unwrap();
+/// ```
+pub fn join_all_indexed<I>(iter: I) -> JoinAllIndexed<<I as IntoIterator>::Item>
+where
+    I: IntoIterator,
+    <I as IntoIterator>::Item: Future,
+{
+    JoinAllIndexed {
+        queue: FuturesUnorderedBounded::from_iter(iter),
+    }
+}
+
+impl<F: Future> Stream for JoinAllIndexed<F> {
+    type Item = (usize, F::Output);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // `from_iter` places each future into the slot matching its position in the iterator.
+        self.queue.poll_inner(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.queue.size_hint()
+    }
+}
+
+impl<F: Future> FusedStream for JoinAllIndexed<F> {
+    fn is_terminated(&self) -> bool {
+        self.queue.is_terminated()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::future::ready;
@@ -127,4 +220,16 @@ mod tests {
         assert_eq!(x.len(), 10);
         assert_eq!(x.capacity(), 10);
     }
+
+    #[test]
+    fn join_all_indexed() {
+        use futures::{Stream, StreamExt};
+
+        let stream = crate::join_all_indexed((0..10).map(|i| ready(i * 2)));
+        assert_eq!(stream.size_hint(), (10, Some(10)));
+
+        let mut x = futures::executor::block_on(stream.collect::<Vec<_>>());
+        x.sort();
+        assert_eq!(x, (0..10).map(|i| (i, i * 2)).collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,95 @@
+//! Helpers for collections that group futures by a user supplied key.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::ready;
+use pin_project_lite::pin_project;
+
+pin_project!(
+    /// A future that returns its key alongside its output.
+    pub(crate) struct Tagged<K, F> {
+        #[pin]
+        future: F,
+        key: Option<K>,
+    }
+);
+
+impl<K, F> Tagged<K, F> {
+    pub(crate) fn new(key: K, future: F) -> Self {
+        Self {
+            future,
+            key: Some(key),
+        }
+    }
+}
+
+impl<K, F: Future> Future for Tagged<K, F> {
+    type Output = (K, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.future.poll(cx));
+        let key = this.key.take().expect("Tagged polled after completion");
+        Poll::Ready((key, output))
+    }
+}
+
+/// Tracks which keys are busy, along with the items waiting for each busy key.
+pub(crate) struct KeyedQueues<K, T> {
+    // a key is busy for as long as it has an entry
+    queues: BTreeMap<K, VecDeque<T>>,
+    waiting: usize,
+}
+
+impl<K: Ord, T> KeyedQueues<K, T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            queues: BTreeMap::new(),
+            waiting: 0,
+        }
+    }
+
+    /// Marks `key` as busy with `item`.
+    ///
+    /// Returns the item back if the key is not busy, so it can be started.
+    /// Otherwise, the item is queued until the key becomes free.
+    pub(crate) fn acquire(&mut self, key: K, item: T) -> Option<T> {
+        match self.queues.get_mut(&key) {
+            Some(queue) => {
+                queue.push_back(item);
+                self.waiting += 1;
+                None
+            }
+            None => {
+                self.queues.insert(key, VecDeque::new());
+                Some(item)
+            }
+        }
+    }
+
+    /// Releases `key` from the item that was busy with it.
+    ///
+    /// Returns the next item waiting on the key, which keeps the key busy.
+    pub(crate) fn release(&mut self, key: &K) -> Option<T> {
+        let queue = self.queues.get_mut(key)?;
+        match queue.pop_front() {
+            Some(item) => {
+                self.waiting -= 1;
+                Some(item)
+            }
+            None => {
+                self.queues.remove(key);
+                None
+            }
+        }
+    }
+
+    /// The number of items waiting for their key to become free.
+    pub(crate) fn waiting(&self) -> usize {
+        self.waiting
+    }
+}
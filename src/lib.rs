@@ -101,26 +101,64 @@ use core::future::Future;
 use futures_core::Stream;
 
 mod arc_slice;
+mod budget;
 mod buffered;
+mod fair_queue;
 mod futures_ordered;
 mod futures_ordered_bounded;
+mod futures_sequenced;
 mod futures_unordered;
 mod futures_unordered_bounded;
+mod futures_unordered_by_key;
+mod head_of_line;
+mod hedge;
+mod instrument;
 mod join_all;
+mod keyed;
 mod merge;
+mod metrics;
+mod priority;
+mod select;
 mod slot_map;
+#[cfg(feature = "stall-detector")]
+mod stall;
+mod timeout;
+mod timer;
 mod try_buffered;
 mod try_join_all;
 
-pub use buffered::{BufferUnordered, BufferedOrdered, BufferedStreamExt};
+pub use budget::PollBudget;
+pub use buffered::{
+    Adaptive, Aimd, BufferUnordered, BufferUnorderedWeighted, BufferedOrdered,
+    BufferedOrderedByKey, BufferedOrderedHeadOfLine, BufferedStreamExt, ConcurrencyLimit,
+};
+pub use fair_queue::FairQueue;
 pub use futures_ordered::FuturesOrdered;
 pub use futures_ordered_bounded::FuturesOrderedBounded;
+pub use futures_sequenced::{FuturesSequenced, Gap, GapPolicy};
 pub use futures_unordered::FuturesUnordered;
-pub use futures_unordered_bounded::FuturesUnorderedBounded;
-pub use join_all::{join_all, JoinAll};
+pub use futures_unordered_bounded::{FuturesUnorderedBounded, FuturesUnorderedBoundedBuilder};
+pub use futures_unordered_by_key::FuturesUnorderedByKey;
+pub use head_of_line::{HeadAction, HeadOfLineOrdered, HeadPolicy, Stalled};
+pub use hedge::{hedge, Hedge};
+pub use instrument::{MakeSpan, NoSpan};
+#[cfg(feature = "tracing")]
+pub use join_all::join_all_with_span;
+pub use join_all::{join_all, join_all_indexed, JoinAll, JoinAllIndexed};
 pub use merge::Merge;
-pub use try_buffered::{BufferedTryStreamExt, TryBufferUnordered, TryBufferedOrdered};
-pub use try_join_all::{try_join_all, TryJoinAll};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use priority::PriorityBuffered;
+pub use select::{select_all, select_ok, SelectAll, SelectOk};
+pub use slot_map::Key;
+#[cfg(feature = "stall-detector")]
+pub use stall::StallReport;
+pub use timeout::{Elapsed, FuturesUnorderedBoundedTimeout};
+pub use timer::{NoTimer, Timer};
+pub use try_buffered::{
+    BufferedTryStreamExt, CollectErrors, FailFast, TryBufferUnordered, TryBufferedOrdered,
+};
+pub use try_join_all::{try_join_all, try_join_all_settled, TryJoinAll, TryJoinAllSettled};
 
 mod private_try_future {
     use core::future::Future;
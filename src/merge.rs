@@ -5,7 +5,7 @@ use core::{
 
 use futures_core::Stream;
 
-use crate::FuturesUnorderedBounded;
+use crate::{FuturesUnorderedBounded, Key, PollBudget};
 
 /// A combined stream that releases values in any order that they come
 ///
@@ -62,14 +62,81 @@ impl<S> Merge<S> {
     /// # Errors
     /// This method will error if the buffer is currently full, returning the future back
     pub fn try_push(&mut self, stream: S) -> Result<(), S> {
-        self.streams.try_push_with(stream, core::convert::identity)
+        self.streams
+            .try_push_with(stream, core::convert::identity)
+            .map(|_| ())
     }
-}
 
-impl<S: Stream> Stream for Merge<S> {
-    type Item = S::Item;
+    /// Push a stream into the set, entering `span` every time the stream is polled.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full. See [`Merge::try_push`] to get a result instead
+    #[cfg(feature = "tracing")]
+    #[track_caller]
+    pub fn push_with_span(&mut self, stream: S, span: tracing::Span) {
+        if self.streams.try_push_with_span(stream, span).is_err() {
+            panic!("attempted to push into a full `Merge`")
+        }
+    }
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    /// Push a stream into the set, returning a [`Key`] that refers to it.
+    ///
+    /// The key can be used to [`cancel`](Merge::cancel) the stream, or to access
+    /// it via [`get_pin_mut`](Merge::get_pin_mut), until it is exhausted.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full. See [`Merge::try_push_keyed`] to get a result instead
+    #[track_caller]
+    pub fn push_keyed(&mut self, stream: S) -> Key {
+        match self.try_push_keyed(stream) {
+            Ok(key) => key,
+            Err(_) => panic!("attempted to push into a full `Merge`"),
+        }
+    }
+
+    /// Push a stream into the set, returning a [`Key`] that refers to it.
+    ///
+    /// # Errors
+    /// This method will error if the buffer is currently full, returning the stream back
+    pub fn try_push_keyed(&mut self, stream: S) -> Result<Key, S> {
+        self.streams.try_push_keyed(stream)
+    }
+
+    /// Returns the poll budget of the set.
+    pub fn poll_budget(&self) -> PollBudget {
+        self.streams.poll_budget()
+    }
+
+    /// Changes how many streams the set polls in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn set_poll_budget(&mut self, budget: PollBudget) {
+        self.streams.set_poll_budget(budget);
+    }
+
+    /// Returns `true` if the stream referred to by `key` is still in the set.
+    pub fn contains(&self, key: Key) -> bool {
+        self.streams.contains(key)
+    }
+
+    /// Returns a pinned mutable reference to the stream referred to by `key`,
+    /// if it is still in the set.
+    pub fn get_pin_mut(&mut self, key: Key) -> Option<Pin<&mut S>> {
+        self.streams.get_pin_mut(key)
+    }
+
+    /// Removes the stream referred to by `key`, dropping it in place.
+    ///
+    /// Returns `false` if the stream was already exhausted or removed.
+    pub fn cancel(&mut self, key: Key) -> bool {
+        self.streams.cancel(key)
+    }
+}
+
+impl<S: Stream> Merge<S> {
+    /// Attempt to pull out the next value of this set, along with the [`Key`]
+    /// of the stream that produced it.
+    pub fn poll_next_keyed(&mut self, cx: &mut Context<'_>) -> Poll<Option<(Key, S::Item)>> {
         loop {
             match self.streams.poll_inner_no_remove(cx, S::poll_next) {
                 // if we have a value from the stream, wake up that slot again
@@ -78,7 +145,7 @@ impl<S: Stream> Stream for Merge<S> {
                     unsafe {
                         self.streams.shared.push(i);
                     }
-                    break Poll::Ready(Some(x));
+                    break Poll::Ready(Some((self.streams.tasks.key(i), x)));
                 }
                 // if a stream completed, remove it from the queue
                 Poll::Ready(Some((i, None))) => {
@@ -91,6 +158,45 @@ impl<S: Stream> Stream for Merge<S> {
     }
 }
 
+impl<S: Stream> Stream for Merge<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.poll_next_keyed(cx) {
+            Poll::Ready(Some((_, x))) => Poll::Ready(Some(x)),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: Stream> Merge<S> {
+    /// Creates a set of the given streams, where each stream is polled inside of a
+    /// [`tracing::Span`](https://docs.rs/tracing/latest/tracing/struct.Span.html).
+    ///
+    /// This behaves like [`Merge::from_iter`], except `span_fn` is called with each stream
+    /// to create the span that it is polled in.
+    ///
+    /// Requires the `tracing` feature.
+    #[cfg(feature = "tracing")]
+    pub fn from_iter_with_span<I, SF>(iter: I, mut span_fn: SF) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        SF: FnMut(&S) -> tracing::Span,
+    {
+        let mut merge = Self::from_iter(iter);
+        let tasks = &mut merge.streams.tasks;
+        for i in 0..tasks.len() {
+            let span = match tasks.get(i) {
+                Some(stream) => span_fn(&stream),
+                None => continue,
+            };
+            tasks.set_span(i, crate::instrument::SlotSpan::new(span));
+        }
+        merge
+    }
+}
+
 impl<S: Stream> FromIterator<S> for Merge<S> {
     fn from_iter<T>(iter: T) -> Self
     where
@@ -112,6 +218,7 @@ mod tests {
     use alloc::rc::Rc;
     use futures::executor::block_on;
     use futures::executor::LocalPool;
+    use futures::future::poll_fn;
     use futures::prelude::*;
     use futures::stream;
     use futures::task::LocalSpawnExt;
@@ -133,6 +240,27 @@ mod tests {
         })
     }
 
+    #[test]
+    fn merge_keyed() {
+        block_on(async {
+            let mut s: Merge<_> = [stream::iter([1, 1]), stream::iter([2, 2])]
+                .into_iter()
+                .collect();
+
+            let (key, n) = poll_fn(|cx| s.poll_next_keyed(cx)).await.unwrap();
+            assert!(s.contains(key));
+            assert!(s.get_pin_mut(key).is_some());
+
+            assert!(s.cancel(key));
+            assert!(!s.contains(key));
+            assert!(!s.cancel(key));
+
+            // only the other stream remains
+            let rest: Vec<_> = s.collect().await;
+            assert_eq!(rest, [3 - n; 2]);
+        })
+    }
+
     /// This test case uses channels so we'll have streams that return Pending from time to time.
     ///
     /// The purpose of this test is to make sure we have the waking logic working.
@@ -244,4 +372,25 @@ mod tests {
             pool.run_until_stalled()
         }
     }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn merge_with_span() {
+        let names = RefCell::new(vec![]);
+        let current_span = |n| {
+            stream::once(async move { n }).inspect(|_| {
+                let span = tracing::Span::current();
+                names.borrow_mut().push(span.metadata().map(|m| m.name()));
+            })
+        };
+
+        tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+            let merge = Merge::from_iter_with_span([current_span(0), current_span(1)], |_| {
+                tracing::info_span!("stream")
+            });
+            assert_eq!(block_on(merge.count()), 2);
+        });
+
+        assert_eq!(names.into_inner(), [Some("stream"), Some("stream")]);
+    }
 }
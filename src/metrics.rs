@@ -0,0 +1,163 @@
+//! Opt-in metrics for [`FuturesUnorderedBounded`] and [`FuturesUnordered`](crate::FuturesUnordered).
+//!
+//! These are only recorded with the `metrics` feature. Otherwise [`Recorder`] is
+//! a zero sized type, and all of its methods compile away.
+
+#[cfg(feature = "metrics")]
+use alloc::vec::Vec;
+
+use crate::{arc_slice::ArcSlice, FuturesUnorderedBounded};
+
+/// A snapshot of the metrics recorded by a [`FuturesUnorderedBounded`] or a
+/// [`FuturesUnordered`](crate::FuturesUnordered).
+///
+/// Requires the `metrics` feature.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// The number of times the futures in each slot have been polled, indexed by slot.
+    ///
+    /// For a [`FuturesUnordered`](crate::FuturesUnordered), this holds the slots of each of
+    /// its current groups in turn.
+    pub slot_polls: Vec<u64>,
+    /// The total number of times a future in the set has been polled.
+    pub polls: u64,
+    /// The number of polls where the future was still pending.
+    pub spurious_polls: u64,
+    /// The number of wakes received from the futures in the set,
+    /// including repeat wakes of a future that was already waiting to be polled.
+    pub wakes: u64,
+    /// The number of times polling the set yielded back to the executor because it had
+    /// polled too many futures in one go.
+    pub budget_yields: u64,
+    /// The number of times polling the set yielded back to the executor because the wake
+    /// queue was in the middle of being updated by another thread.
+    pub inconsistent_retries: u64,
+    /// The number of [`FuturesUnorderedBounded`] groups making up the set.
+    pub groups: usize,
+    /// The number of bytes allocated for the futures and their wake queues.
+    pub allocated_bytes: usize,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    /// Adds the metrics of a group to this snapshot.
+    pub(crate) fn merge(&mut self, group: Metrics) {
+        self.slot_polls.extend(group.slot_polls);
+        self.polls += group.polls;
+        self.spurious_polls += group.spurious_polls;
+        self.wakes += group.wakes;
+        self.budget_yields += group.budget_yields;
+        self.inconsistent_retries += group.inconsistent_retries;
+        self.groups += group.groups;
+        self.allocated_bytes += group.allocated_bytes;
+    }
+}
+
+/// Records the metrics of a set as it is polled.
+#[cfg(feature = "metrics")]
+pub(crate) struct Recorder {
+    slot_polls: Vec<u64>,
+    polls: u64,
+    spurious_polls: u64,
+    budget_yields: u64,
+    inconsistent_retries: u64,
+    // wakes received by wake queues that have since been dropped
+    dropped_wakes: u64,
+}
+
+/// Records the metrics of a set as it is polled.
+#[cfg(not(feature = "metrics"))]
+pub(crate) struct Recorder;
+
+#[cfg(feature = "metrics")]
+impl Recorder {
+    pub(crate) const fn new() -> Self {
+        Self {
+            slot_polls: Vec::new(),
+            polls: 0,
+            spurious_polls: 0,
+            budget_yields: 0,
+            inconsistent_retries: 0,
+            dropped_wakes: 0,
+        }
+    }
+
+    /// Makes room to record the polls of `slots` slots.
+    pub(crate) fn grow(&mut self, slots: usize) {
+        if slots > self.slot_polls.len() {
+            self.slot_polls.resize(slots, 0);
+        }
+    }
+
+    pub(crate) fn poll(&mut self, index: usize, ready: bool) {
+        self.slot_polls[index] += 1;
+        self.polls += 1;
+        if !ready {
+            self.spurious_polls += 1;
+        }
+    }
+
+    pub(crate) fn budget_yield(&mut self) {
+        self.budget_yields += 1;
+    }
+
+    pub(crate) fn inconsistent_retry(&mut self) {
+        self.inconsistent_retries += 1;
+    }
+
+    /// Keeps hold of the wakes received by a wake queue that is about to be dropped.
+    pub(crate) fn drop_queue(&mut self, queue: &ArcSlice) {
+        self.dropped_wakes += queue.wakes();
+    }
+
+    /// Keeps hold of the metrics of a group that is about to be dropped.
+    pub(crate) fn drop_group<F>(&mut self, group: &FuturesUnorderedBounded<F>) {
+        let metrics = group.metrics();
+        self.polls += metrics.polls;
+        self.spurious_polls += metrics.spurious_polls;
+        self.budget_yields += metrics.budget_yields;
+        self.inconsistent_retries += metrics.inconsistent_retries;
+        self.dropped_wakes += metrics.wakes;
+    }
+
+    /// The metrics recorded so far, without any of the live state of the set.
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            slot_polls: self.slot_polls.clone(),
+            polls: self.polls,
+            spurious_polls: self.spurious_polls,
+            wakes: self.dropped_wakes,
+            budget_yields: self.budget_yields,
+            inconsistent_retries: self.inconsistent_retries,
+            groups: 0,
+            allocated_bytes: 0,
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl Recorder {
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn grow(&mut self, _slots: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn poll(&mut self, _index: usize, _ready: bool) {}
+
+    #[inline(always)]
+    pub(crate) fn budget_yield(&mut self) {}
+
+    #[inline(always)]
+    pub(crate) fn inconsistent_retry(&mut self) {}
+
+    #[inline(always)]
+    pub(crate) fn drop_queue(&mut self, _queue: &ArcSlice) {}
+
+    #[inline(always)]
+    pub(crate) fn drop_group<F>(&mut self, _group: &FuturesUnorderedBounded<F>) {}
+}
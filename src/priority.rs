@@ -0,0 +1,277 @@
+use alloc::collections::BinaryHeap;
+use core::{
+    cmp::{Ordering, Reverse},
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_core::{FusedStream, Stream};
+
+use crate::FuturesUnorderedBounded;
+
+/// A future waiting to be admitted into a [`PriorityBuffered`].
+struct Prioritized<F> {
+    rank: i128,
+    seq: u64,
+    future: F,
+}
+
+impl<F> PartialEq for Prioritized<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<F> Eq for Prioritized<F> {}
+
+impl<F> PartialOrd for Prioritized<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F> Ord for Prioritized<F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max heap, so the highest rank is admitted first,
+        // and then the earliest pushed.
+        (self.rank, Reverse(self.seq)).cmp(&(other.rank, Reverse(other.seq)))
+    }
+}
+
+/// A bounded set of futures which admits the highest priority futures first.
+///
+/// Up to `capacity` futures run at once, like in [`FuturesUnorderedBounded`]. Futures pushed
+/// while the set is full wait, and whenever a running future completes, the waiting future
+/// with the highest priority is started. Futures with equal priority are started in the order
+/// they were pushed.
+///
+/// To prevent low priority futures from waiting forever, [`aging`](PriorityBuffered::aging)
+/// raises the priority of waiting futures as other futures are started ahead of them.
+///
+/// # Example
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::StreamExt;
+/// use futures_buffered::PriorityBuffered;
+/// use std::future::ready;
+///
+/// let mut set = PriorityBuffered::new(1);
+/// set.push(0, ready("first"));
+/// set.push(0, ready("batch"));
+/// set.push(10, ready("interactive"));
+///
+/// let outputs: Vec<_> = set.collect().await;
+/// assert_eq!(outputs, ["first", "interactive", "batch"]);
+/// # });
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub struct PriorityBuffered<F> {
+    in_progress_queue: FuturesUnorderedBounded<F>,
+    pending: BinaryHeap<Prioritized<F>>,
+    aging: Option<u32>,
+    // the number of futures pushed so far, used to start equal priorities in push order
+    tick: u64,
+    // the number of waiting futures started so far, used to age the waiting futures
+    admitted: u64,
+}
+
+impl<F> Unpin for PriorityBuffered<F> {}
+
+impl<F> PriorityBuffered<F> {
+    /// Constructs a new, empty `PriorityBuffered` which runs up to `capacity` futures at once.
+    ///
+    /// The returned set does not contain any futures.
+    /// In this state, [`PriorityBuffered::poll_next`](Stream::poll_next) will
+    /// return [`Poll::Ready(None)`](Poll::Ready).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            in_progress_queue: FuturesUnorderedBounded::new(capacity),
+            pending: BinaryHeap::new(),
+            aging: None,
+            tick: 0,
+            admitted: 0,
+        }
+    }
+
+    /// Ages waiting futures, so that a waiting future gains one priority level for every
+    /// `admissions` waiting futures that are started while it waits.
+    ///
+    /// Age is counted in futures started from the waiting queue, not in time, so a future
+    /// only ages while the set is being polled and its running futures are completing.
+    ///
+    /// # Panics
+    /// This method will panic if `admissions` is 0, or if any futures have already been pushed.
+    #[track_caller]
+    pub fn aging(mut self, admissions: u32) -> Self {
+        assert!(
+            admissions > 0,
+            "aging needs at least one admission per priority level"
+        );
+        assert!(self.tick == 0, "aging must be set before pushing futures");
+        self.aging = Some(admissions);
+        self
+    }
+
+    /// Push a future into the set with the given priority. Higher priorities are started first.
+    ///
+    /// The future is started straight away if fewer than `capacity` futures are running.
+    ///
+    /// This function will not call `poll` on the submitted future. The caller
+    /// must ensure that [`PriorityBuffered::poll_next`](Stream::poll_next) is called
+    /// in order to receive wake-up notifications for the given future.
+    pub fn push(&mut self, priority: i32, fut: F) {
+        let seq = self.tick;
+        self.tick += 1;
+
+        let fut = if self.pending.is_empty() {
+            match self.in_progress_queue.try_push(fut) {
+                Ok(()) => return,
+                Err(fut) => fut,
+            }
+        } else {
+            fut
+        };
+
+        // With aging, waiting futures are ordered by
+        // `priority + (admitted - admitted_at_push) / admissions` at the current admission,
+        // which is the same order as `priority * admissions - admitted_at_push`.
+        let rank = match self.aging {
+            Some(admissions) => {
+                i128::from(priority) * i128::from(admissions) - i128::from(self.admitted)
+            }
+            None => i128::from(priority),
+        };
+        self.pending.push(Prioritized {
+            rank,
+            seq,
+            future: fut,
+        });
+    }
+
+    /// Returns `true` if the set contains no futures
+    pub fn is_empty(&self) -> bool {
+        self.in_progress_queue.is_empty() && self.pending.is_empty()
+    }
+
+    /// Returns the number of futures contained in the set, both running and waiting.
+    pub fn len(&self) -> usize {
+        self.in_progress_queue.len() + self.pending.len()
+    }
+
+    /// Returns the number of futures waiting to be started.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of futures that can run at once.
+    pub fn capacity(&self) -> usize {
+        self.in_progress_queue.capacity()
+    }
+
+    fn admit(&mut self) {
+        while self.in_progress_queue.len() < self.in_progress_queue.capacity() {
+            match self.pending.pop() {
+                Some(next) => {
+                    self.admitted += 1;
+                    self.in_progress_queue.push(next.future);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<F: Future> Stream for PriorityBuffered<F> {
+    type Item = F::Output;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        this.admit();
+        match this.in_progress_queue.poll_inner(cx) {
+            Poll::Ready(Some((_, output))) => {
+                // a slot has freed up for the next waiting future
+                this.admit();
+                Poll::Ready(Some(output))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<F: Future> FusedStream for PriorityBuffered<F> {
+    fn is_terminated(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<F> fmt::Debug for PriorityBuffered<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PriorityBuffered {{ ... }}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::ready;
+    use futures::StreamExt;
+    use futures_test::task::noop_context;
+
+    #[test]
+    fn priority() {
+        let mut set = PriorityBuffered::new(2);
+        for (i, priority) in [0, 0, 1, 5, 1, 5, 3].into_iter().enumerate() {
+            set.push(priority, ready(i));
+        }
+        assert_eq!(set.pending_len(), 5);
+        assert_eq!(set.len(), 7);
+
+        let mut cx = noop_context();
+        let mut outputs = vec![];
+        while let Poll::Ready(Some(x)) = set.poll_next_unpin(&mut cx) {
+            outputs.push(x);
+        }
+        // the first two start straight away, then by priority and push order
+        assert_eq!(outputs, [0, 1, 3, 5, 6, 2, 4]);
+    }
+
+    #[test]
+    fn aging() {
+        let mut set = PriorityBuffered::new(1).aging(2);
+        set.push(0, ready(0));
+        set.push(0, ready(1));
+
+        let mut cx = noop_context();
+        let mut outputs = vec![];
+        for i in 2..8 {
+            set.push(2, ready(i));
+            if let Poll::Ready(Some(x)) = set.poll_next_unpin(&mut cx) {
+                outputs.push(x);
+            }
+        }
+        while let Poll::Ready(Some(x)) = set.poll_next_unpin(&mut cx) {
+            outputs.push(x);
+        }
+        // future 1 has waited for 4 admissions by the time future 6 is pushed,
+        // so it catches up with the later high priority futures
+        assert_eq!(outputs, [0, 2, 3, 4, 5, 1, 6, 7]);
+
+        // futures pushed together age together
+        let mut set = PriorityBuffered::new(1).aging(1);
+        set.push(0, ready(0));
+        set.push(0, ready(1));
+        for i in 2..5 {
+            set.push(1, ready(i));
+        }
+        let outputs: Vec<_> = futures::executor::block_on(set.collect());
+        assert_eq!(outputs, [0, 2, 3, 4, 1]);
+    }
+}
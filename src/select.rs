@@ -0,0 +1,200 @@
+use alloc::vec::Vec;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{FuturesUnorderedBounded, TryFuture};
+
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// Future for the [`select_all`] function.
+pub struct SelectAll<F> {
+    queue: FuturesUnorderedBounded<F>,
+}
+
+impl<F> Unpin for SelectAll<F> {}
+
+/// Creates a new future which will select over a list of futures.
+///
+/// The returned future will wait for any future within `iter` to be ready. Upon
+/// completion the item resolved will be returned, along with the index of the
+/// future that was ready and the set of all the remaining futures.
+///
+/// The remaining futures keep the same slots they had in the original set, so
+/// further outputs polled from it with [`poll_next_keyed`](FuturesUnorderedBounded::poll_next_keyed)
+/// can still be attributed to their original position.
+///
+/// # Panics
+///
+/// This function will panic if the iterator specified contains no items.
+///
+/// # Examples
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::channel::oneshot;
+/// use futures::stream::StreamExt;
+/// use futures_buffered::select_all;
+///
+/// let (send_one, recv_one) = oneshot::channel();
+/// let (send_two, recv_two) = oneshot::channel();
+///
+/// send_two.send(2i32)?;
+/// let (output, index, mut remaining) = select_all([recv_one, recv_two]).await;
+/// assert_eq!(output, Ok(2));
+/// assert_eq!(index, 1);
+/// assert_eq!(remaining.len(), 1);
+///
+/// send_one.send(1i32)?;
+/// assert_eq!(remaining.next().await, Some(Ok(1)));
+/// # Ok::<(), i32>(()) }).unwrap();
+/// ```
+pub fn select_all<I>(iter: I) -> SelectAll<<I as IntoIterator>::Item>
+where
+    I: IntoIterator,
+    <I as IntoIterator>::Item: Future,
+{
+    let queue = FuturesUnorderedBounded::from_iter(iter);
+    assert!(!queue.is_empty(), "select_all requires at least one future");
+    SelectAll { queue }
+}
+
+impl<F: Future> Future for SelectAll<F> {
+    type Output = (F::Output, usize, FuturesUnorderedBounded<F>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.queue.poll_inner(cx) {
+            Poll::Ready(Some((i, x))) => {
+                let remaining =
+                    core::mem::replace(&mut self.queue, FuturesUnorderedBounded::new(0));
+                Poll::Ready((x, i, remaining))
+            }
+            Poll::Ready(None) => panic!("SelectAll polled after completion"),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// Future for the [`select_ok`] function.
+pub struct SelectOk<F: TryFuture> {
+    queue: FuturesUnorderedBounded<F>,
+    errors: Vec<(usize, F::Err)>,
+}
+
+impl<F: TryFuture> Unpin for SelectOk<F> {}
+
+/// Creates a new future which will select the first successful future over a list of futures.
+///
+/// The returned future will wait for any future within `iter` to be ready and `Ok`.
+/// Unlike [`select_all`], this will only return the first successful completion,
+/// or all of the errors if every future fails. The errors are returned in the same
+/// order as the futures were provided.
+///
+/// Once a future succeeds, all the remaining futures are dropped.
+///
+/// # Examples
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures_buffered::select_ok;
+///
+/// async fn foo(i: u32) -> Result<u32, u32> {
+///     if i < 4 { Ok(i) } else { Err(i) }
+/// }
+///
+/// let futures = vec![foo(5), foo(2), foo(6)];
+/// assert_eq!(select_ok(futures).await, Ok(2));
+///
+/// let futures = vec![foo(4), foo(5), foo(6)];
+/// assert_eq!(select_ok(futures).await, Err(vec![4, 5, 6]));
+/// # });
+/// ```
+pub fn select_ok<I>(iter: I) -> SelectOk<<I as IntoIterator>::Item>
+where
+    I: IntoIterator,
+    <I as IntoIterator>::Item: TryFuture,
+{
+    SelectOk {
+        queue: FuturesUnorderedBounded::from_iter(iter),
+        errors: Vec::new(),
+    }
+}
+
+impl<F: TryFuture> Future for SelectOk<F> {
+    type Output = Result<F::Ok, Vec<F::Err>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match self.queue.poll_inner(cx) {
+                Poll::Ready(Some((_, Ok(t)))) => {
+                    self.queue.clear();
+                    self.errors.clear();
+                    break Poll::Ready(Ok(t));
+                }
+                Poll::Ready(Some((i, Err(e)))) => {
+                    self.errors.push((i, e));
+                }
+                Poll::Ready(None) => {
+                    let mut errors = core::mem::take(&mut self.errors);
+                    errors.sort_unstable_by_key(|(i, _)| *i);
+                    break Poll::Ready(Err(errors.into_iter().map(|(_, e)| e).collect()));
+                }
+                Poll::Pending => break Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::ready;
+    use futures::{channel::oneshot, executor::block_on, StreamExt};
+
+    #[test]
+    fn select_all() {
+        let (send0, recv0) = oneshot::channel::<i32>();
+        let (send1, recv1) = oneshot::channel::<i32>();
+        let (send2, recv2) = oneshot::channel::<i32>();
+
+        send1.send(1).unwrap();
+        let (x, i, mut remaining) = block_on(crate::select_all([recv0, recv1, recv2]));
+        assert_eq!(x, Ok(1));
+        assert_eq!(i, 1);
+        assert_eq!(remaining.len(), 2);
+
+        send2.send(2).unwrap();
+        assert_eq!(block_on(remaining.next()), Some(Ok(2)));
+
+        drop(send0);
+        assert!(block_on(remaining.next()).unwrap().is_err());
+        assert!(block_on(remaining.next()).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn select_all_empty() {
+        drop(crate::select_all(core::iter::empty::<
+            core::future::Ready<()>,
+        >()));
+    }
+
+    #[test]
+    fn select_ok() {
+        let x = block_on(crate::select_ok(
+            (0..10).map(|i| ready(if i == 7 { Ok(i) } else { Err(i) })),
+        ));
+        assert_eq!(x, Ok(7));
+
+        let x = block_on(crate::select_ok(
+            (0..10).map(|i| ready(Result::<(), _>::Err(i))),
+        ));
+        assert_eq!(x, Err((0..10).collect::<Vec<_>>()));
+
+        let x = block_on(crate::select_ok(core::iter::empty::<
+            core::future::Ready<Result<(), ()>>,
+        >()));
+        assert_eq!(x, Err(vec![]));
+    }
+}
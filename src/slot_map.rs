@@ -5,126 +5,295 @@
 use alloc::{boxed::Box, vec::Vec};
 use core::{hint::unreachable_unchecked, pin::Pin};
 
+use crate::instrument::SlotSpan;
+
 pub(crate) struct SlotMap<F> {
     slots: Pin<Box<[Slot<F>]>>,
+    // storage appended by `grow`, as values already pinned in `slots` can't be moved.
+    extra: Vec<Pin<Box<[Slot<F>]>>>,
     free_head: usize,
     filled: usize,
+    allocated: usize,
+    limit: usize,
+}
+
+/// A handle to a single future that was pushed into a set.
+///
+/// Keys remain valid until the future they point to completes or is cancelled.
+/// Once that slot is reused by another future, the old key will no longer match it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub(crate) index: usize,
+    pub(crate) generation: u32,
 }
 
 // A slot, which represents storage for a value and a current version.
 // Can be occupied or vacant.
-enum Slot<F> {
+struct Slot<F> {
+    generation: u32,
+    value: SlotValue<F>,
+    // entered while polling the value. This is not pinned
+    span: SlotSpan,
+}
+
+enum SlotValue<F> {
     Occupied(F),
     NextFree(usize),
 }
 
+impl<F> Slot<F> {
+    fn value(self: Pin<&mut Self>) -> Pin<&mut SlotValue<F>> {
+        // SAFETY: the value is structurally pinned
+        unsafe { self.map_unchecked_mut(|slot| &mut slot.value) }
+    }
+}
+
 impl<F> SlotMap<F> {
     /// Constructs a new, empty [`SlotMap`] with the given capacity
     pub fn new(capacity: usize) -> Self {
-        let slots: Vec<_> = (1..=capacity).map(Slot::NextFree).collect();
-
         Self {
-            slots: slots.into_boxed_slice().into(),
+            slots: Self::free_slots(0, capacity),
+            extra: Vec::new(),
             free_head: 0,
             filled: 0,
+            allocated: capacity,
+            limit: capacity,
+        }
+    }
+
+    fn free_slots(start: usize, end: usize) -> Pin<Box<[Slot<F>]>> {
+        let slots: Vec<_> = (start + 1..=end)
+            .map(|next_free| Slot {
+                generation: 0,
+                value: SlotValue::NextFree(next_free),
+                span: SlotSpan::NONE,
+            })
+            .collect();
+        slots.into_boxed_slice().into()
+    }
+
+    /// Allocates more slots such that the slot map can hold `capacity` values,
+    /// without moving any of the existing values.
+    ///
+    /// At least doubles the allocated slots, so that only a logarithmic number of chunks
+    /// ever need to be walked to find a slot.
+    pub fn grow(&mut self, capacity: usize) {
+        if capacity <= self.allocated {
+            return;
         }
+        let capacity = usize::max(capacity, self.allocated * 2);
+        // The end of the free list is always marked by `allocated`, which will
+        // now point at the first of the new slots.
+        self.extra.push(Self::free_slots(self.allocated, capacity));
+        self.allocated = capacity;
+    }
+
+    /// Sets the maximum number of values that can be inserted.
+    ///
+    /// This can be lower than the number of allocated slots.
+    pub fn set_limit(&mut self, limit: usize) {
+        debug_assert!(limit <= self.allocated);
+        self.limit = limit;
     }
 
     /// Inserts a value given by `f` into the slot map.
-    pub fn insert_with<Arg>(
-        &mut self,
-        arg: Arg,
-        mut f: impl FnMut(Arg) -> F,
-    ) -> Result<usize, Arg> {
-        let key = self.free_head;
-        let Some(mut slot) = self.get_slot(key) else {
+    pub fn insert_with<Arg>(&mut self, arg: Arg, mut f: impl FnMut(Arg) -> F) -> Result<Key, Arg> {
+        if self.filled >= self.limit {
+            return Err(arg);
+        }
+        let index = self.free_head;
+        let Some(mut slot) = self.get_slot(index) else {
             return Err(arg);
         };
 
-        let Slot::NextFree(next_free) = *slot else {
+        let SlotValue::NextFree(next_free) = slot.value else {
             debug_assert!(false, "slotmap free_head pointed to a not free entry");
             unsafe { unreachable_unchecked() }
         };
 
-        slot.set(Slot::Occupied(f(arg)));
+        let generation = slot.generation;
+        slot.as_mut().value().set(SlotValue::Occupied(f(arg)));
 
         self.free_head = next_free;
         self.filled += 1;
 
-        Ok(key)
+        Ok(Key { index, generation })
     }
 
     /// Removes a key from the slot map
-    pub fn remove(&mut self, key: usize) {
+    pub fn remove(&mut self, index: usize) {
         let free_head = self.free_head;
-        let Some(mut slot) = self.get_slot(key) else {
+        let Some(mut slot) = self.get_slot(index) else {
             return;
         };
-        if let Slot::NextFree(_) = &*slot {
+        if let SlotValue::NextFree(_) = slot.value {
             return; // don't update if this slot is already free
         }
-        slot.set(Slot::NextFree(free_head));
-        self.free_head = key;
+        slot.as_mut().value().set(SlotValue::NextFree(free_head));
+        // SAFETY: the generation and span are not pinned
+        unsafe {
+            let slot = slot.get_unchecked_mut();
+            slot.generation = slot.generation.wrapping_add(1);
+            slot.span = SlotSpan::NONE;
+        }
+        self.free_head = index;
         self.filled -= 1;
     }
 
-    fn get_slot(&mut self, key: usize) -> Option<Pin<&mut Slot<F>>> {
+    /// Removes the value for the given key from the slot map, if the key is still valid.
+    pub fn remove_key(&mut self, key: Key) -> bool {
+        if self.contains_key(key) {
+            self.remove(key.index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes every value from the slot map
+    pub fn clear(&mut self) {
+        for index in 0..self.allocated {
+            self.remove(index);
+        }
+    }
+
+    fn get_slot(&mut self, mut index: usize) -> Option<Pin<&mut Slot<F>>> {
+        let mut slots = self.slots.as_mut();
+        if index >= slots.len() {
+            index -= slots.len();
+            let mut extra = self.extra.iter_mut();
+            slots = loop {
+                let chunk = extra.next()?.as_mut();
+                if index < chunk.len() {
+                    break chunk;
+                }
+                index -= chunk.len();
+            };
+        }
         // SAFETY: We return the inner data pinned and we never move the values within
         unsafe {
-            let slots = self.slots.as_mut().get_unchecked_mut();
-            let slot = slots.get_mut(key)?;
+            let slots = slots.get_unchecked_mut();
+            let slot = slots.get_unchecked_mut(index);
             Some(Pin::new_unchecked(slot))
         }
     }
 
-    pub fn get(&mut self, key: usize) -> Option<Pin<&mut F>> {
-        let slot = self.get_slot(key)?;
+    fn slot(&self, mut index: usize) -> Option<&Slot<F>> {
+        if let Some(slot) = self.slots.get(index) {
+            return Some(slot);
+        }
+        index -= self.slots.len();
+        for chunk in &self.extra {
+            if let Some(slot) = chunk.get(index) {
+                return Some(slot);
+            }
+            index -= chunk.len();
+        }
+        None
+    }
+
+    pub fn get(&mut self, index: usize) -> Option<Pin<&mut F>> {
+        let slot = self.get_slot(index)?;
         // SAFETY: We return the inner data pinned and we never move the values within
         unsafe {
-            match slot.get_unchecked_mut() {
-                Slot::Occupied(f) => Some(Pin::new_unchecked(f)),
-                Slot::NextFree(_) => None,
+            match slot.value().get_unchecked_mut() {
+                SlotValue::Occupied(f) => Some(Pin::new_unchecked(f)),
+                SlotValue::NextFree(_) => None,
             }
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.filled
+    /// Returns the value at `index`, along with the span to enter while polling it.
+    pub fn get_with_span(&mut self, index: usize) -> Option<(Pin<&mut F>, &SlotSpan)> {
+        let slot = self.get_slot(index)?;
+        // SAFETY: We return the inner data pinned and we never move the values within.
+        // The span is not pinned
+        unsafe {
+            let slot = slot.get_unchecked_mut();
+            match &mut slot.value {
+                SlotValue::Occupied(f) => Some((Pin::new_unchecked(f), &slot.span)),
+                SlotValue::NextFree(_) => None,
+            }
+        }
     }
 
-    pub fn capacity(&self) -> usize {
-        self.slots.len()
+    /// Sets the span to enter while polling the value at `index`.
+    ///
+    /// The span is dropped when the value is removed.
+    pub fn set_span(&mut self, index: usize, span: SlotSpan) {
+        if let Some(slot) = self.get_slot(index) {
+            // SAFETY: the span is not pinned
+            unsafe { slot.get_unchecked_mut().span = span }
+        }
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.filled == 0
+    pub fn get_by_key(&mut self, key: Key) -> Option<Pin<&mut F>> {
+        if self.contains_key(key) {
+            self.get(key.index)
+        } else {
+            None
+        }
     }
 
-    pub fn iter_mut(&mut self) -> SlotMapIterMut<'_, F> {
-        // SAFETY: Our iterator will return pinned values
-        SlotMapIterMut(unsafe { self.slots.as_mut().get_unchecked_mut().iter_mut() })
+    /// Returns the key for the value currently stored at `index`.
+    pub fn key(&self, index: usize) -> Key {
+        let slot = self.slot(index).expect("index should be within capacity");
+        Key {
+            index,
+            generation: slot.generation,
+        }
     }
-}
 
-pub(crate) struct SlotMapIterMut<'a, F>(core::slice::IterMut<'a, Slot<F>>);
-impl<'a, F> Iterator for SlotMapIterMut<'a, F> {
-    type Item = Pin<&'a mut F>;
+    /// Returns `true` if there is a value at `index`.
+    pub fn is_occupied(&self, index: usize) -> bool {
+        self.slot(index)
+            .is_some_and(|slot| matches!(slot.value, SlotValue::Occupied(_)))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        for f in self.0.by_ref() {
-            if let Slot::Occupied(f) = f {
-                // SAFETY: These values are guaranteed pinned
-                return Some(unsafe { Pin::new_unchecked(f) });
+    pub fn contains_key(&self, key: Key) -> bool {
+        match self.slot(key.index) {
+            Some(slot) => {
+                slot.generation == key.generation && matches!(slot.value, SlotValue::Occupied(_))
             }
+            None => false,
         }
-        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.filled
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.limit
+    }
+
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// The number of bytes allocated for the slots.
+    #[cfg(feature = "metrics")]
+    pub fn allocated_bytes(&self) -> usize {
+        core::mem::size_of::<Slot<F>>() * self.allocated
+            + core::mem::size_of::<Pin<Box<[Slot<F>]>>>() * self.extra.capacity()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filled == 0
     }
 }
 
 impl<F> FromIterator<F> for SlotMap<F> {
     fn from_iter<T: IntoIterator<Item = F>>(iter: T) -> Self {
         // store the futures in our task list
-        let inner: Box<[Slot<F>]> = iter.into_iter().map(Slot::Occupied).collect();
+        let inner: Box<[Slot<F>]> = iter
+            .into_iter()
+            .map(|f| Slot {
+                generation: 0,
+                value: SlotValue::Occupied(f),
+                span: SlotSpan::NONE,
+            })
+            .collect();
 
         // determine the actual capacity and create the shared state
         let cap = inner.len();
@@ -132,8 +301,11 @@ impl<F> FromIterator<F> for SlotMap<F> {
         // create the queue
         Self {
             slots: inner.into(),
+            extra: Vec::new(),
             free_head: cap,
             filled: cap,
+            allocated: cap,
+            limit: cap,
         }
     }
 }
@@ -0,0 +1,159 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A problem found by the stall detector of a [`FuturesUnorderedBounded`](crate::FuturesUnorderedBounded).
+///
+/// See [`FuturesUnorderedBounded::detect_stalls`](crate::FuturesUnorderedBounded::detect_stalls).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StallReport {
+    /// The future in `slot` returned [`Pending`](core::task::Poll::Pending) without keeping
+    /// a clone of its waker or waking itself, so nothing can wake it up again.
+    LostWakeup {
+        /// The index of the slot holding the future.
+        slot: usize,
+    },
+    /// The future in `slot` has not been woken for `polls` polls of the set.
+    Idle {
+        /// The index of the slot holding the future.
+        slot: usize,
+        /// The number of times the set was polled since the future was last woken.
+        polls: usize,
+    },
+}
+
+impl fmt::Display for StallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StallReport::LostWakeup { slot } => write!(
+                f,
+                "future in slot {slot} returned pending without keeping its waker"
+            ),
+            StallReport::Idle { slot, polls } => write!(
+                f,
+                "future in slot {slot} has not been woken for {polls} polls"
+            ),
+        }
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+// A node in the list of waiting slots
+#[derive(Clone, Copy)]
+struct Link {
+    prev: usize,
+    next: usize,
+    // the set poll at which the slot was last woken
+    woken: usize,
+    linked: bool,
+}
+
+impl Link {
+    const UNLINKED: Self = Self {
+        prev: NIL,
+        next: NIL,
+        woken: 0,
+        linked: false,
+    };
+}
+
+/// Tracks when each slot was last woken, and collects the stall reports.
+///
+/// The waiting slots are kept in a list ordered by when they were last woken, so each poll
+/// of the set only needs to look at the slots that have just become idle.
+pub(crate) struct StallDetector {
+    idle_polls: usize,
+    // the number of times the set has been polled
+    polls: usize,
+    links: Vec<Link>,
+    // the least and most recently woken slots that have not been reported
+    head: usize,
+    tail: usize,
+    reports: Vec<StallReport>,
+}
+
+impl StallDetector {
+    pub(crate) fn new(idle_polls: usize, slots: usize) -> Self {
+        let mut this = Self {
+            idle_polls,
+            polls: 0,
+            links: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            reports: Vec::new(),
+        };
+        this.grow(slots);
+        this
+    }
+
+    pub(crate) fn grow(&mut self, slots: usize) {
+        if slots > self.links.len() {
+            self.links.resize(slots, Link::UNLINKED);
+        }
+    }
+
+    /// Records another poll of the set, reporting any slots that have been idle for too long.
+    ///
+    /// `waiting` should return `true` for the slots that hold a future which is not queued.
+    pub(crate) fn poll(&mut self, mut waiting: impl FnMut(usize) -> bool) {
+        self.polls += 1;
+        while self.head != NIL {
+            let slot = self.head;
+            let polls = self.polls - self.links[slot].woken;
+            if polls < self.idle_polls {
+                break;
+            }
+            // slots that were woken or removed are linked again when they are next polled
+            self.unlink(slot);
+            if waiting(slot) {
+                self.reports.push(StallReport::Idle { slot, polls });
+            }
+        }
+    }
+
+    /// Records that the slot was woken, and is about to be polled.
+    pub(crate) fn woken(&mut self, slot: usize) {
+        self.unlink(slot);
+        let tail = self.tail;
+        self.links[slot] = Link {
+            prev: tail,
+            next: NIL,
+            woken: self.polls,
+            linked: true,
+        };
+        match tail {
+            NIL => self.head = slot,
+            tail => self.links[tail].next = slot,
+        }
+        self.tail = slot;
+    }
+
+    pub(crate) fn lost_wakeup(&mut self, slot: usize) {
+        // the slot will not be reported again as idle
+        self.unlink(slot);
+        self.reports.push(StallReport::LostWakeup { slot });
+    }
+
+    pub(crate) fn take_reports(&mut self) -> Vec<StallReport> {
+        core::mem::take(&mut self.reports)
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let Link {
+            prev, next, linked, ..
+        } = self.links[slot];
+        if !linked {
+            return;
+        }
+        match prev {
+            NIL => self.head = next,
+            prev => self.links[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.links[next].prev = prev,
+        }
+        self.links[slot] = Link::UNLINKED;
+    }
+}
@@ -0,0 +1,330 @@
+use alloc::collections::BinaryHeap;
+use core::{
+    cmp::Reverse,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{FuturesUnorderedBounded, Key, Timer};
+
+/// Error returned by [`FuturesUnorderedBoundedTimeout`] when a future misses its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed {
+    /// The index of the slot that the future occupied.
+    pub index: usize,
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl core::error::Error for Elapsed {}
+
+pin_project!(
+    /// A bounded set of futures, each with their own deadline.
+    ///
+    /// This behaves like [`FuturesUnorderedBounded`], but every future is given a deadline
+    /// when it is pushed. Futures that have not completed by their deadline are dropped,
+    /// and the set yields `Err(Elapsed)` in their place.
+    ///
+    /// Rather than a timer per future, the whole set shares a single timer from `T`,
+    /// which is re-armed to the nearest deadline.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[tokio::main] async fn main() {
+    /// use futures::StreamExt;
+    /// use futures_buffered::FuturesUnorderedBoundedTimeout;
+    /// use std::time::Duration;
+    ///
+    /// // `Tokio` implements `Timer`, as shown in the `Timer` example
+    #[doc = include_str!("doc/tokio_timer.md")]
+    ///
+    /// let mut set = FuturesUnorderedBoundedTimeout::new(2, Tokio);
+    /// set.push_timeout(tokio::time::sleep(Duration::from_secs(60)), Duration::from_millis(10));
+    /// set.push_timeout(tokio::time::sleep(Duration::from_millis(1)), Duration::from_secs(60));
+    ///
+    /// let mut set = std::pin::pin!(set);
+    /// assert_eq!(set.next().await, Some(Ok(())));
+    /// assert!(set.next().await.unwrap().is_err());
+    /// assert_eq!(set.next().await, None);
+    /// # }
+    /// ```
+    pub struct FuturesUnorderedBoundedTimeout<F, T: Timer> {
+        queue: FuturesUnorderedBounded<F>,
+        deadlines: BinaryHeap<Reverse<(T::Instant, Key)>>,
+        timer: T,
+        #[pin]
+        sleep: Option<T::Sleep>,
+        armed: Option<T::Instant>,
+    }
+);
+
+impl<F, T: Timer> FuturesUnorderedBoundedTimeout<F, T> {
+    /// Constructs a new, empty `FuturesUnorderedBoundedTimeout` with the given fixed capacity,
+    /// using `timer` to track the deadlines.
+    ///
+    /// The returned set does not contain any futures.
+    /// In this state, [`FuturesUnorderedBoundedTimeout::poll_next`](Stream::poll_next) will
+    /// return [`Poll::Ready(None)`](Poll::Ready).
+    pub fn new(cap: usize, timer: T) -> Self {
+        Self {
+            queue: FuturesUnorderedBounded::new(cap),
+            deadlines: BinaryHeap::new(),
+            timer,
+            sleep: None,
+            armed: None,
+        }
+    }
+
+    /// Push a future into the set, which must complete within `timeout`.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full. See [`FuturesUnorderedBoundedTimeout::try_push_timeout`] to get a result instead
+    #[track_caller]
+    pub fn push_timeout(&mut self, fut: F, timeout: Duration) -> Key {
+        let deadline = self.timer.now() + timeout;
+        self.push_until(fut, deadline)
+    }
+
+    /// Push a future into the set, which must complete within `timeout`.
+    ///
+    /// This function submits the given future to the set for managing.
+    /// This function will not call [`poll`](Future::poll) on the submitted
+    /// future. The caller must ensure that [`FuturesUnorderedBoundedTimeout::poll_next`](Stream::poll_next) is called
+    /// in order to receive wake-up notifications for the given future.
+    ///
+    /// # Errors
+    /// This method will error if the buffer is currently full, returning the future back
+    pub fn try_push_timeout(&mut self, fut: F, timeout: Duration) -> Result<Key, F> {
+        let deadline = self.timer.now() + timeout;
+        self.try_push_until(fut, deadline)
+    }
+
+    /// Push a future into the set, which must complete by `deadline`.
+    ///
+    /// # Panics
+    /// This method will panic if the buffer is currently full. See [`FuturesUnorderedBoundedTimeout::try_push_until`] to get a result instead
+    #[track_caller]
+    pub fn push_until(&mut self, fut: F, deadline: T::Instant) -> Key {
+        match self.try_push_until(fut, deadline) {
+            Ok(key) => key,
+            Err(_) => panic!("attempted to push into a full `FuturesUnorderedBoundedTimeout`"),
+        }
+    }
+
+    /// Push a future into the set, which must complete by `deadline`.
+    ///
+    /// # Errors
+    /// This method will error if the buffer is currently full, returning the future back
+    pub fn try_push_until(&mut self, fut: F, deadline: T::Instant) -> Result<Key, F> {
+        let key = self.queue.try_push_keyed(fut)?;
+
+        // deadlines of futures that completed early are only removed lazily,
+        // so clear them out before they can outnumber the futures.
+        if self.deadlines.len() >= 2 * self.queue.capacity().max(1) {
+            let queue = &self.queue;
+            self.deadlines.retain(|Reverse((_, k))| queue.contains(*k));
+        }
+
+        self.deadlines.push(Reverse((deadline, key)));
+        Ok(key)
+    }
+
+    /// Returns `true` if the future associated with `key` is still in the set.
+    pub fn contains(&self, key: Key) -> bool {
+        self.queue.contains(key)
+    }
+
+    /// Drops the future associated with `key`, if it is still in the set.
+    ///
+    /// Returns `true` if a future was removed.
+    pub fn cancel(&mut self, key: Key) -> bool {
+        self.queue.cancel(key)
+    }
+
+    /// Returns `true` if the set contains no futures
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns the number of futures contained in the set.
+    ///
+    /// This represents the total number of in-flight futures.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the number of futures that can be contained in the set.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<F: Future, T: Timer> FuturesUnorderedBoundedTimeout<F, T> {
+    /// Attempt to pull out the next value of this set, along with the [`Key`]
+    /// of the future that produced it.
+    ///
+    /// Futures that missed their deadline produce `Err(Elapsed)`.
+    #[allow(clippy::type_complexity)]
+    pub fn poll_next_keyed(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<(Key, Result<F::Output, Elapsed>)>> {
+        let mut this = self.project();
+
+        // once the timer fires, every deadline up to the one it was armed for has passed,
+        // even if the clock disagrees
+        let mut fired = None;
+        loop {
+            match this.queue.poll_next_keyed(cx) {
+                Poll::Ready(Some((key, x))) => return Poll::Ready(Some((key, Ok(x)))),
+                Poll::Ready(None) => {
+                    this.deadlines.clear();
+                    this.sleep.set(None);
+                    *this.armed = None;
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => {}
+            }
+
+            let now = match fired {
+                Some(fired) => this.timer.now().max(fired),
+                None => this.timer.now(),
+            };
+
+            let deadline = loop {
+                let Some(&Reverse((deadline, key))) = this.deadlines.peek() else {
+                    break None;
+                };
+                if !this.queue.contains(key) {
+                    // the future has already completed or was cancelled
+                    this.deadlines.pop();
+                } else if deadline <= now {
+                    this.deadlines.pop();
+                    this.queue.cancel(key);
+                    return Poll::Ready(Some((key, Err(Elapsed { index: key.index }))));
+                } else {
+                    break Some(deadline);
+                }
+            };
+
+            let Some(deadline) = deadline else {
+                this.sleep.set(None);
+                *this.armed = None;
+                return Poll::Pending;
+            };
+
+            // re-arm the timer if the nearest deadline has changed
+            if *this.armed != Some(deadline) {
+                this.sleep.set(Some(this.timer.sleep_until(deadline)));
+                *this.armed = Some(deadline);
+            }
+
+            match this.sleep.as_mut().as_pin_mut() {
+                Some(sleep) => match sleep.poll(cx) {
+                    Poll::Ready(_) => {
+                        this.sleep.set(None);
+                        *this.armed = None;
+                        fired = Some(deadline);
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<F: Future, T: Timer> Stream for FuturesUnorderedBoundedTimeout<F, T> {
+    type Item = Result<F::Output, Elapsed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.poll_next_keyed(cx) {
+            Poll::Ready(Some((_, x))) => Poll::Ready(Some(x)),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use futures::{channel::oneshot, StreamExt};
+    use futures_test::task::noop_context;
+
+    use super::*;
+    use crate::timer::mock::{MockInstant, MockTimer};
+
+    #[test]
+    fn timeouts() {
+        let mut cx = noop_context();
+        let timer = MockTimer::default();
+
+        let mut set = FuturesUnorderedBoundedTimeout::new(3, timer.clone());
+        let (send0, recv0) = oneshot::channel::<i32>();
+        let (_send1, recv1) = oneshot::channel::<i32>();
+        let (_send2, recv2) = oneshot::channel::<i32>();
+
+        let key0 = set.push_timeout(recv0, Duration::from_millis(10));
+        let key1 = set.push_timeout(recv1, Duration::from_millis(20));
+        let key2 = set.push_until(recv2, MockInstant(30));
+        let mut set = core::pin::pin!(set);
+
+        assert!(set.as_mut().poll_next(&mut cx).is_pending());
+
+        // completes before the deadline
+        send0.send(0).unwrap();
+        timer.advance(5);
+        assert_eq!(
+            set.as_mut().poll_next_keyed(&mut cx),
+            Poll::Ready(Some((key0, Ok(Ok(0)))))
+        );
+        assert!(set.as_mut().poll_next(&mut cx).is_pending());
+
+        // the stale deadline of the completed future is skipped
+        timer.advance(10);
+        assert!(set.as_mut().poll_next(&mut cx).is_pending());
+
+        timer.advance(10);
+        assert_eq!(
+            set.as_mut().poll_next_keyed(&mut cx),
+            Poll::Ready(Some((key1, Err(Elapsed { index: key1.index }))))
+        );
+        assert!(!set.contains(key1));
+        assert_eq!(set.len(), 1);
+
+        // cancelled futures don't time out
+        assert!(set.cancel(key2));
+        timer.advance(100);
+        assert_eq!(set.as_mut().poll_next(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn stale_deadlines_are_compacted() {
+        let timer = MockTimer::default();
+        let mut set = FuturesUnorderedBoundedTimeout::new(2, timer);
+
+        for i in 0..100 {
+            set.push_timeout(core::future::ready(i), Duration::from_secs(1));
+            let x = futures::executor::block_on(set.next());
+            assert_eq!(x, Some(Ok(i)));
+        }
+
+        assert!(set.deadlines.len() <= 4);
+    }
+}
@@ -0,0 +1,125 @@
+use core::{future::Future, ops::Add, time::Duration};
+
+/// A source of time, used by the collections in this crate that support deadlines.
+///
+/// This keeps the crate independent of any particular runtime.
+///
+/// # Examples
+///
+/// ```
+/// use futures_buffered::Timer;
+///
+/// #[derive(Clone, Copy)]
+/// struct Tokio;
+///
+/// impl Timer for Tokio {
+///     type Instant = tokio::time::Instant;
+///     type Sleep = tokio::time::Sleep;
+///
+///     fn now(&self) -> Self::Instant {
+///         tokio::time::Instant::now()
+///     }
+///
+///     fn sleep_until(&self, deadline: Self::Instant) -> Self::Sleep {
+///         tokio::time::sleep_until(deadline)
+///     }
+/// }
+/// ```
+pub trait Timer {
+    /// A point in time. Adding a [`Duration`] to it must move it into the future.
+    type Instant: Ord + Copy + Add<Duration, Output = Self::Instant>;
+
+    /// A future that completes once a deadline has been reached.
+    type Sleep: Future;
+
+    /// Returns the current time.
+    fn now(&self) -> Self::Instant;
+
+    /// Creates a future that completes once `deadline` has been reached.
+    fn sleep_until(&self, deadline: Self::Instant) -> Self::Sleep;
+}
+
+/// A [`Timer`] that never fires, for policies that wait forever.
+///
+/// See [`GapPolicy::wait`](crate::GapPolicy::wait).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoTimer;
+
+impl Timer for NoTimer {
+    type Instant = Duration;
+    type Sleep = core::future::Pending<()>;
+
+    fn now(&self) -> Self::Instant {
+        Duration::ZERO
+    }
+
+    fn sleep_until(&self, _deadline: Self::Instant) -> Self::Sleep {
+        core::future::pending()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use core::{
+        future::Future,
+        ops::Add,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+    use std::{cell::Cell, rc::Rc};
+
+    /// A manually advanced clock, counting in milliseconds.
+    #[derive(Clone, Default)]
+    pub(crate) struct MockTimer(Rc<Cell<u64>>);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub(crate) struct MockInstant(pub(crate) u64);
+
+    pub(crate) struct MockSleep {
+        now: Rc<Cell<u64>>,
+        deadline: u64,
+    }
+
+    impl MockTimer {
+        pub(crate) fn advance(&self, millis: u64) {
+            self.0.set(self.0.get() + millis);
+        }
+    }
+
+    impl Add<Duration> for MockInstant {
+        type Output = MockInstant;
+
+        fn add(self, rhs: Duration) -> MockInstant {
+            MockInstant(self.0 + rhs.as_millis() as u64)
+        }
+    }
+
+    impl Future for MockSleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if self.now.get() >= self.deadline {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl super::Timer for MockTimer {
+        type Instant = MockInstant;
+        type Sleep = MockSleep;
+
+        fn now(&self) -> MockInstant {
+            MockInstant(self.0.get())
+        }
+
+        fn sleep_until(&self, deadline: MockInstant) -> MockSleep {
+            MockSleep {
+                now: self.0.clone(),
+                deadline: deadline.0,
+            }
+        }
+    }
+}
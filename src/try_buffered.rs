@@ -1,12 +1,14 @@
+use alloc::vec::Vec;
 use core::{
+    mem,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use crate::{FuturesOrderedBounded, TryStream};
-use crate::{FuturesUnorderedBounded, TryFuture};
+use crate::{buffered::LimitListener, FuturesUnorderedBounded, PollBudget, TryFuture};
+use crate::{Adaptive, Aimd, FuturesOrderedBounded, TryStream};
 use futures_core::ready;
-use futures_core::Stream;
+use futures_core::{FusedStream, Stream};
 use pin_project_lite::pin_project;
 
 impl<T: ?Sized + TryStream> BufferedTryStreamExt for T {}
@@ -51,8 +53,155 @@ pub trait BufferedTryStreamExt: TryStream {
         TryBufferUnordered {
             stream: Some(self),
             in_progress_queue: FuturesUnorderedBounded::new(n),
+            limit: None,
+            fail_fast: None,
         }
     }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), which
+    /// stops at the first error.
+    ///
+    /// This behaves like [`try_buffered_unordered`](BufferedTryStreamExt::try_buffered_unordered),
+    /// but once an error is returned, either from this stream or from one of the futures,
+    /// no more items will be pulled from this stream. With [`FailFast::Cancel`] all in-flight
+    /// futures are dropped and the stream ends after yielding the error. With [`FailFast::Drain`]
+    /// the in-flight futures are still driven to completion and their outputs are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::{BufferedTryStreamExt, FailFast};
+    /// use std::future::ready;
+    ///
+    /// let results = [Ok(1), Err(2), Ok(3)].map(|res| Ok(ready(res)));
+    /// let buffered = stream::iter(results).try_buffered_unordered_fail_fast(1, FailFast::Cancel);
+    /// assert_eq!(buffered.collect::<Vec<_>>().await, [Ok(1), Err(2)]);
+    /// # })
+    /// ```
+    fn try_buffered_unordered_fail_fast(self, n: usize, mode: FailFast) -> TryBufferUnordered<Self>
+    where
+        Self::Ok: TryFuture<Err = Self::Err>,
+        Self: Sized,
+    {
+        TryBufferUnordered {
+            stream: Some(self),
+            in_progress_queue: FuturesUnorderedBounded::new(n),
+            limit: None,
+            fail_fast: Some(mode),
+        }
+    }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), which
+    /// collects every error until the end of the stream.
+    ///
+    /// This behaves like [`try_buffered_unordered`](BufferedTryStreamExt::try_buffered_unordered),
+    /// but errors are not returned as they happen. Instead, once this stream and all the futures
+    /// have completed, every error is returned together as a single `Err(Vec<_>)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::BufferedTryStreamExt;
+    /// use std::future::ready;
+    ///
+    /// let results = [Ok(1), Err(2), Ok(3), Err(4)].map(|res| Ok(ready(res)));
+    /// let buffered = stream::iter(results).try_buffered_unordered_collect_errors(1);
+    /// assert_eq!(buffered.collect::<Vec<_>>().await, [Ok(1), Ok(3), Err(vec![2, 4])]);
+    /// # })
+    /// ```
+    fn try_buffered_unordered_collect_errors(self, n: usize) -> CollectErrors<Self>
+    where
+        Self::Ok: TryFuture<Err = Self::Err>,
+        Self: Sized,
+    {
+        CollectErrors {
+            stream: self.try_buffered_unordered(n),
+            errors: Vec::new(),
+        }
+    }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), where the
+    /// concurrency limit adapts to how the futures are behaving.
+    ///
+    /// This behaves like [`try_buffered_unordered`](BufferedTryStreamExt::try_buffered_unordered),
+    /// but every `Ok` output raises the limit and every `Err` output lowers it, according to
+    /// the given [`Aimd`] configuration.
+    /// See [`try_buffered_unordered_adaptive_by`](BufferedTryStreamExt::try_buffered_unordered_adaptive_by)
+    /// to decide which outputs lower the limit.
+    ///
+    /// Room for the maximum limit is allocated up front, so changing the limit never allocates.
+    #[allow(clippy::type_complexity)]
+    fn try_buffered_unordered_adaptive(
+        self,
+        aimd: Aimd,
+    ) -> Adaptive<
+        TryBufferUnordered<Self>,
+        fn(&Result<<Self::Ok as TryFuture>::Ok, Self::Err>) -> bool,
+    >
+    where
+        Self::Ok: TryFuture<Err = Self::Err>,
+        Self: Sized,
+    {
+        self.try_buffered_unordered_adaptive_by(aimd, Result::is_ok)
+    }
+
+    /// An adaptor for creating a buffered list of pending futures (unordered), where the
+    /// concurrency limit adapts to a custom signal from the outputs.
+    ///
+    /// This behaves like [`try_buffered_unordered_adaptive`](BufferedTryStreamExt::try_buffered_unordered_adaptive),
+    /// but `classify` is called on each output to decide whether it was successful. This
+    /// allows backing off only on some errors, such as overload errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # futures::executor::block_on(async {
+    /// use futures::stream::{self, StreamExt};
+    /// use futures_buffered::{Aimd, BufferedTryStreamExt};
+    /// use std::future::ready;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Error {
+    ///     NotFound,
+    ///     TooManyRequests,
+    /// }
+    ///
+    /// let responses = [Ok(1), Err(Error::NotFound), Err(Error::TooManyRequests)];
+    /// let mut buffered = stream::iter(responses.map(|res| Ok(ready(res))))
+    ///     .try_buffered_unordered_adaptive_by(Aimd::new(1, 4).initial(2), |res: &Result<_, _>| {
+    ///         !matches!(res, Err(Error::TooManyRequests))
+    ///     });
+    ///
+    /// let mut limits = vec![];
+    /// while let Some(_) = buffered.next().await {
+    ///     limits.push(buffered.limit());
+    /// }
+    /// assert_eq!(limits, [3, 4, 2]);
+    /// # })
+    /// ```
+    fn try_buffered_unordered_adaptive_by<C>(
+        self,
+        aimd: Aimd,
+        classify: C,
+    ) -> Adaptive<TryBufferUnordered<Self>, C>
+    where
+        Self::Ok: TryFuture<Err = Self::Err>,
+        C: FnMut(&Result<<Self::Ok as TryFuture>::Ok, Self::Err>) -> bool,
+        Self: Sized,
+    {
+        let (limit, in_progress_queue) = aimd.limited_set();
+        let stream = TryBufferUnordered {
+            stream: Some(self),
+            in_progress_queue,
+            limit: Some(limit.listen()),
+            fail_fast: None,
+        };
+        Adaptive::new(stream, limit, aimd, classify)
+    }
 }
 
 pin_project! {
@@ -69,6 +218,20 @@ pin_project! {
     }
 }
 
+impl<St> TryBufferedOrdered<St>
+where
+    St: TryStream,
+    St::Ok: TryFuture,
+{
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.in_progress_queue.set_poll_budget(budget);
+        self
+    }
+}
+
 impl<St> Stream for TryBufferedOrdered<St>
 where
     St: TryStream,
@@ -82,7 +245,7 @@ where
         // First up, try to spawn off as many futures as possible by filling up
         // our queue of futures.
         let ordered = this.in_progress_queue;
-        while ordered.in_progress_queue.tasks.len() < ordered.in_progress_queue.tasks.capacity() {
+        while ordered.has_room() {
             if let Some(s) = this.stream.as_mut().as_pin_mut() {
                 match s.poll_next(cx)? {
                     Poll::Ready(Some(fut)) => {
@@ -134,22 +297,51 @@ pin_project!(
         #[pin]
         stream: Option<S>,
         in_progress_queue: FuturesUnorderedBounded<S::Ok>,
+        limit: Option<LimitListener>,
+        fail_fast: Option<FailFast>,
     }
 );
 
-impl<St> Stream for TryBufferUnordered<St>
+/// What [`try_buffered_unordered_fail_fast`](BufferedTryStreamExt::try_buffered_unordered_fail_fast)
+/// should do with the in-flight futures after the first error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailFast {
+    /// Drop all in-flight futures and end the stream after returning the error.
+    Cancel,
+    /// Keep returning the outputs of the in-flight futures until they have all completed.
+    Drain,
+}
+
+impl<St: TryStream> TryBufferUnordered<St> {
+    /// Changes how many futures are polled in one go, before yielding back to the executor.
+    ///
+    /// See [`PollBudget`] for more details.
+    pub fn with_poll_budget(mut self, budget: PollBudget) -> Self {
+        self.in_progress_queue.set_poll_budget(budget);
+        self
+    }
+}
+
+impl<St> TryBufferUnordered<St>
 where
     St: TryStream,
     St::Ok: TryFuture<Err = St::Err>,
 {
-    type Item = Result<<St::Ok as TryFuture>::Ok, St::Err>;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    fn poll_buffered(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
         let mut this = self.project();
 
         // First up, try to spawn off as many futures as possible by filling up
         // our queue of futures.
         let unordered = this.in_progress_queue;
+        if let Some(limit) = this.limit {
+            let limit = limit.poll_get(cx);
+            if limit != unordered.capacity() {
+                unordered.set_capacity(limit);
+            }
+        }
         while unordered.tasks.len() < unordered.tasks.capacity() {
             if let Some(s) = this.stream.as_mut().as_pin_mut() {
                 match s.poll_next(cx)? {
@@ -177,6 +369,30 @@ where
             Poll::Pending
         }
     }
+}
+
+impl<St> Stream for TryBufferUnordered<St>
+where
+    St: TryStream,
+    St::Ok: TryFuture<Err = St::Err>,
+{
+    type Item = Result<<St::Ok as TryFuture>::Ok, St::Err>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let res = self.as_mut().poll_buffered(cx);
+        if let Poll::Ready(Some(Err(_))) = res {
+            let mut this = self.project();
+            match this.fail_fast {
+                Some(FailFast::Cancel) => {
+                    this.stream.set(None);
+                    this.in_progress_queue.clear();
+                }
+                Some(FailFast::Drain) => this.stream.set(None),
+                None => {}
+            }
+        }
+        res
+    }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         match &self.stream {
@@ -195,13 +411,73 @@ where
     }
 }
 
+impl<St> FusedStream for TryBufferUnordered<St>
+where
+    St: TryStream,
+    St::Ok: TryFuture<Err = St::Err>,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_none() && self.in_progress_queue.is_empty()
+    }
+}
+
+pin_project!(
+    /// Stream for the [`try_buffered_unordered_collect_errors`](BufferedTryStreamExt::try_buffered_unordered_collect_errors) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct CollectErrors<S: TryStream> {
+        #[pin]
+        stream: TryBufferUnordered<S>,
+        errors: Vec<S::Err>,
+    }
+);
+
+impl<St> Stream for CollectErrors<St>
+where
+    St: TryStream,
+    St::Ok: TryFuture<Err = St::Err>,
+{
+    type Item = Result<<St::Ok as TryFuture>::Ok, Vec<St::Err>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(x)) => return Poll::Ready(Some(Ok(x))),
+                Some(Err(e)) => this.errors.push(e),
+                None if this.errors.is_empty() => return Poll::Ready(None),
+                None => return Poll::Ready(Some(Err(mem::take(this.errors)))),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // all of the errors are returned together, after the last output
+        let (lower, upper) = self.stream.size_hint();
+        let pending = usize::from(!self.errors.is_empty());
+        let lower = usize::max(lower.min(1), pending);
+        let upper = upper.and_then(|upper| upper.checked_add(pending));
+        (lower, upper)
+    }
+}
+
+impl<St> FusedStream for CollectErrors<St>
+where
+    St: TryStream,
+    St::Ok: TryFuture<Err = St::Err>,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated() && self.errors.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use core::task::Poll;
+    use core::{cell::Cell, task::Poll};
     use futures::{
         channel::oneshot::{self, Canceled},
-        stream, TryFutureExt, TryStreamExt,
+        stream::{self, FusedStream},
+        FutureExt, StreamExt, TryFutureExt, TryStreamExt,
     };
     use futures_test::task::noop_context;
 
@@ -293,4 +569,101 @@ mod tests {
         // completes properly
         assert_eq!(buffered.try_poll_next_unpin(&mut cx), Poll::Ready(None));
     }
+
+    #[test]
+    fn buffered_unordered_fail_fast() {
+        struct DropFlag<'a>(&'a Cell<bool>);
+        impl Drop for DropFlag<'_> {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+
+        for mode in [FailFast::Cancel, FailFast::Drain] {
+            let (send_one, recv_one) = oneshot::channel();
+            let (send_two, recv_two) = oneshot::channel();
+            let dropped = Cell::new(false);
+
+            let one = async {
+                let _flag = DropFlag(&dropped);
+                recv_one.await.unwrap_or(Ok(0))
+            };
+            let two = async { recv_two.await.unwrap_or(Ok(0)) };
+            let three = async { Ok(3) };
+            let mut items = vec![
+                Ok(one.boxed_local()),
+                Ok(two.boxed_local()),
+                Ok(three.boxed_local()),
+                Err(0),
+            ]
+            .into_iter();
+
+            // counts how many times the source stream is polled
+            let source_polls = Cell::new(0);
+            let source = stream::poll_fn(|_| {
+                source_polls.set(source_polls.get() + 1);
+                Poll::Ready(items.next())
+            });
+            let mut buffered = source.try_buffered_unordered_fail_fast(2, mode);
+            let mut cx = noop_context();
+
+            assert_eq!(buffered.try_poll_next_unpin(&mut cx), Poll::Pending);
+            assert_eq!(source_polls.get(), 2);
+
+            send_two.send(Err(2)).unwrap();
+            assert_eq!(
+                buffered.try_poll_next_unpin(&mut cx),
+                Poll::Ready(Some(Err(2)))
+            );
+            assert_eq!(source_polls.get(), 2);
+
+            if mode == FailFast::Drain {
+                assert!(!dropped.get());
+                assert_eq!(buffered.try_poll_next_unpin(&mut cx), Poll::Pending);
+                send_one.send(Ok(1)).unwrap();
+                assert_eq!(
+                    buffered.try_poll_next_unpin(&mut cx),
+                    Poll::Ready(Some(Ok(1)))
+                );
+            } else {
+                // the in-flight future was dropped along with the error
+                assert!(dropped.get());
+            }
+            assert_eq!(buffered.try_poll_next_unpin(&mut cx), Poll::Ready(None));
+
+            // the source stream is never polled again
+            assert_eq!(source_polls.get(), 2);
+        }
+    }
+
+    #[test]
+    fn buffered_unordered_collect_errors() {
+        let (send_one, recv_one) = oneshot::channel();
+        let (send_two, recv_two) = oneshot::channel();
+
+        let stream_of_futures = stream::iter(vec![
+            Ok(recv_one.unwrap_or_else(_else)),
+            Err(0),
+            Ok(recv_two.unwrap_or_else(_else)),
+        ]);
+        let mut buffered = stream_of_futures.try_buffered_unordered_collect_errors(10);
+        let mut cx = noop_context();
+
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Pending);
+
+        send_two.send(Ok(2)).unwrap();
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(Some(Ok(2))));
+
+        assert_eq!(buffered.size_hint(), (1, Some(1)));
+
+        send_one.send(Err(1)).unwrap();
+        assert!(!buffered.is_terminated());
+        assert_eq!(
+            buffered.poll_next_unpin(&mut cx),
+            Poll::Ready(Some(Err(vec![0, 1])))
+        );
+        assert_eq!(buffered.size_hint(), (0, Some(0)));
+        assert!(buffered.is_terminated());
+        assert_eq!(buffered.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
 }
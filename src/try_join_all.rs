@@ -100,9 +100,133 @@ impl<F: TryFuture> Future for TryJoinAll<F> {
     }
 }
 
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// Future for the [`try_join_all_settled`] function.
+pub struct TryJoinAllSettled<F: TryFuture> {
+    queue: FuturesUnorderedBounded<F>,
+    output: Box<[MaybeUninit<F::Ok>]>,
+    errors: Vec<(usize, F::Err)>,
+}
+
+impl<F: TryFuture> Unpin for TryJoinAllSettled<F> {}
+
+/// Creates a future which represents either a collection of the outputs of the futures
+/// given, or a collection of all the errors.
+///
+/// The returned future will drive execution for all of its underlying futures,
+/// even if some of them return an error.
+///
+/// If all futures complete successfully, then the returned future will succeed with a
+/// `Vec` of all the successful results, in the same order as they were provided.
+/// Otherwise, the returned future will fail with a `Vec` of every error, paired with the
+/// index of the future that returned it, in the same order as they were provided.
+///
+/// # Examples
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures_buffered::try_join_all_settled;
+///
+/// async fn foo(i: u32) -> Result<u32, u32> {
+///     if i < 4 { Ok(i) } else { Err(i) }
+/// }
+///
+/// let futures = vec![foo(1), foo(2), foo(3)];
+/// assert_eq!(try_join_all_settled(futures).await, Ok(vec![1, 2, 3]));
+///
+/// let futures = vec![foo(1), foo(5), foo(3), foo(4)];
+/// assert_eq!(try_join_all_settled(futures).await, Err(vec![(1, 5), (3, 4)]));
+/// # });
+/// ```
+pub fn try_join_all_settled<I>(iter: I) -> TryJoinAllSettled<<I as IntoIterator>::Item>
+where
+    I: IntoIterator,
+    <I as IntoIterator>::Item: TryFuture,
+{
+    // create the queue
+    let queue = FuturesUnorderedBounded::from_iter(iter);
+
+    // create the output buffer
+    let mut output = Vec::with_capacity(queue.capacity());
+    output.resize_with(queue.capacity(), MaybeUninit::uninit);
+
+    TryJoinAllSettled {
+        queue,
+        output: output.into_boxed_slice(),
+        errors: Vec::new(),
+    }
+}
+
+impl<F: TryFuture> Future for TryJoinAllSettled<F> {
+    type Output = Result<Vec<F::Ok>, Vec<(usize, F::Err)>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match self.as_mut().queue.poll_inner(cx) {
+                Poll::Ready(Some((i, Ok(t)))) => {
+                    self.output[i].write(t);
+                }
+                Poll::Ready(Some((i, Err(e)))) => {
+                    self.errors.push((i, e));
+                }
+                Poll::Ready(None) => {
+                    // take the boxed slice
+                    let boxed = core::mem::replace(&mut self.output, Vec::new().into_boxed_slice());
+
+                    if self.errors.is_empty() {
+                        // SAFETY: for Ready(None) to be returned, we know that every future in the queue
+                        // must be consumed. Since we have a 1:1 mapping in the queue to our output and there
+                        // were no errors, we know that every output entry is init.
+                        let boxed = unsafe {
+                            // Box::assume_init
+                            let raw = Box::into_raw(boxed);
+                            Box::from_raw(raw as *mut [F::Ok])
+                        };
+
+                        break Poll::Ready(Ok(boxed.into_vec()));
+                    }
+
+                    let mut errors = core::mem::take(&mut self.errors);
+                    errors.sort_unstable_by_key(|(i, _)| *i);
+
+                    // drop the successful outputs, which are all the entries without an error
+                    let mut failed = errors.iter().map(|(i, _)| *i).peekable();
+                    for (i, output) in boxed.into_vec().iter_mut().enumerate() {
+                        if failed.next_if_eq(&i).is_none() {
+                            // SAFETY: every entry without an error has been init
+                            unsafe { output.assume_init_drop() }
+                        }
+                    }
+
+                    break Poll::Ready(Err(errors));
+                }
+                Poll::Pending => break Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<F: TryFuture> Drop for TryJoinAllSettled<F> {
+    fn drop(&mut self) {
+        // only the futures that have completed without an error have written their output.
+        // Once the outputs have been returned, this is empty.
+        self.errors.sort_unstable_by_key(|(i, _)| *i);
+        let mut failed = self.errors.iter().map(|(i, _)| *i).peekable();
+        for (i, output) in self.output.iter_mut().enumerate() {
+            if failed.next_if_eq(&i).is_none() && !self.queue.tasks.is_occupied(i) {
+                // SAFETY: the future for this entry completed successfully
+                unsafe { output.assume_init_drop() }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::future::ready;
+    use futures::{channel::oneshot, FutureExt};
+    use futures_test::task::noop_context;
+    use std::rc::Rc;
 
     #[test]
     fn try_join_all() {
@@ -119,4 +243,46 @@ mod tests {
         ))
         .unwrap_err();
     }
+
+    #[test]
+    fn try_join_all_settled() {
+        let x = futures::executor::block_on(crate::try_join_all_settled(
+            (0..10).map(|i| ready(Result::<_, ()>::Ok(i))),
+        ))
+        .unwrap();
+
+        assert_eq!(x, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(x.capacity(), 10);
+
+        let errors = futures::executor::block_on(crate::try_join_all_settled(
+            (0..10).map(|i| ready(if i % 3 == 0 { Err(i * 10) } else { Ok(vec![i]) })),
+        ))
+        .unwrap_err();
+
+        assert_eq!(errors, [(0, 0), (3, 30), (6, 60), (9, 90)]);
+    }
+
+    #[test]
+    fn try_join_all_settled_drop_early() {
+        let output = Rc::new(());
+        let (_tx, rx) = oneshot::channel::<()>();
+        let futures = vec![
+            ready(Ok(output.clone())).boxed_local(),
+            ready(Err(())).boxed_local(),
+            async {
+                let _ = rx.await;
+                Ok(Rc::new(()))
+            }
+            .boxed_local(),
+            ready(Ok(output.clone())).boxed_local(),
+        ];
+
+        let mut join = crate::try_join_all_settled(futures);
+        assert!(join.poll_unpin(&mut noop_context()).is_pending());
+        assert_eq!(Rc::strong_count(&output), 3);
+
+        // the completed outputs are dropped along with the future
+        drop(join);
+        assert_eq!(Rc::strong_count(&output), 1);
+    }
 }